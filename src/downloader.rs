@@ -1,4 +1,20 @@
+pub mod category;
+mod constant;
+mod dash;
+mod detector;
 pub mod dto;
+pub mod extractor;
+pub mod import;
+mod limiter;
 pub mod manager;
+mod metalink;
+mod output;
+pub mod profile;
 mod progress;
 mod segment;
+pub mod sink;
+pub mod storage;
+pub mod strategy;
+mod template;
+pub mod upload;
+pub mod writer;
@@ -0,0 +1,102 @@
+use super::strategy::Probe;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CategoryRule {
+    pub name: String,
+    pub mime_types: Vec<String>,
+    pub extensions: Vec<String>,
+    pub hosts: Vec<String>,
+    pub url_pattern: Option<String>,
+    pub dir: Option<String>,
+    pub filename_template: Option<String>,
+    pub max_concurrent_count: Option<usize>,
+    pub speed_limit: Option<u64>,
+}
+
+impl CategoryRule {
+    // Every condition that is set has to match, a rule without conditions matches everything.
+    pub fn matches(&self, probe: &Probe) -> bool {
+        self.matches_mime_type(probe.content_type.as_deref())
+            && self.matches_extension(&probe.filename)
+            && self.matches_host(&probe.url)
+            && self.matches_url(&probe.url)
+    }
+
+    fn matches_mime_type(&self, content_type: Option<&str>) -> bool {
+        if self.mime_types.is_empty() {
+            return true;
+        }
+        let Some(content_type) = content_type
+            .and_then(|ct| ct.split(';').next())
+            .map(|ct| ct.trim().to_ascii_lowercase())
+        else {
+            return false;
+        };
+        self.mime_types.iter().any(|pattern| {
+            let pattern = pattern.to_ascii_lowercase();
+            match pattern.strip_suffix("/*") {
+                Some(kind) => content_type.split('/').next() == Some(kind),
+                None => content_type == pattern,
+            }
+        })
+    }
+
+    fn matches_extension(&self, filename: &str) -> bool {
+        if self.extensions.is_empty() {
+            return true;
+        }
+        let Some(extension) = Path::new(filename).extension() else {
+            return false;
+        };
+        let extension = extension.to_string_lossy();
+        self.extensions
+            .iter()
+            .any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(&extension))
+    }
+
+    fn matches_host(&self, url: &str) -> bool {
+        matches_hosts(&self.hosts, url)
+    }
+
+    fn matches_url(&self, url: &str) -> bool {
+        matches_url_pattern(
+            self.url_pattern.as_deref(),
+            url,
+            &format!("category {}", self.name),
+        )
+    }
+}
+
+pub(super) fn matches_hosts(hosts: &[String], url: &str) -> bool {
+    if hosts.is_empty() {
+        return true;
+    }
+    let Some(host) = url::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+    else {
+        return false;
+    };
+    // "example.com" also covers its sub-domains such as "cdn.example.com".
+    hosts.iter().any(|pattern| {
+        let pattern = pattern.to_ascii_lowercase();
+        host == pattern || host.ends_with(&format!(".{}", pattern))
+    })
+}
+
+pub(super) fn matches_url_pattern(pattern: Option<&str>, url: &str, name: &str) -> bool {
+    match pattern {
+        Some(pattern) => match Regex::new(pattern) {
+            Ok(regex) => regex.is_match(url),
+            Err(e) => {
+                eprintln!("Invalid url pattern in {}: {}", name, e);
+                false
+            }
+        },
+        None => true,
+    }
+}
@@ -0,0 +1,43 @@
+pub const HLS_CONTENT_TYPES: [&str; 4] = [
+    "application/vnd.apple.mpegurl",
+    "application/x-mpegurl",
+    "audio/mpegurl",
+    "audio/x-mpegurl",
+];
+pub const DASH_CONTENT_TYPES: [&str; 1] = ["application/dash+xml"];
+pub const METALINK_CONTENT_TYPES: [&str; 2] =
+    ["application/metalink4+xml", "application/metalink+xml"];
+pub const PAGE_CONTENT_TYPES: [&str; 2] = ["text/html", "application/xhtml+xml"];
+pub const SNIFFABLE_CONTENT_TYPES: [&str; 6] = [
+    "text/plain",
+    "text/xml",
+    "application/xml",
+    "application/octet-stream",
+    "binary/octet-stream",
+    "application/x-unknown",
+];
+pub const HLS_EXTENSIONS: [&str; 2] = ["m3u8", "m3u"];
+pub const DASH_EXTENSIONS: [&str; 1] = ["mpd"];
+pub const METALINK_EXTENSIONS: [&str; 2] = ["meta4", "metalink"];
+pub const SNIFF_LENGTH: usize = 1024;
+pub const MAX_NESTING_DEPTH: usize = 4;
+pub const DEFAULT_FILENAME: &str = "downloaded_file";
+pub const EXPLICIT_NAME_TEMPLATE: &str = "{filename}";
+pub const PART_EXTENSION: &str = "part";
+pub const MAX_FILENAME_LENGTH: usize = 255;
+pub const MAX_EXTENSION_LENGTH: usize = 16;
+pub const COUNTER_PLACEHOLDER: &str = "{n}";
+pub const MAX_NAME_COLLISIONS: usize = 10_000;
+pub const RESERVED_FILENAME_CHARS: [char; 9] = ['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+pub const RESERVED_FILENAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+pub const STREAM_SPACE_OVERHEAD_DIVISOR: u64 = 10;
+pub const DISK_SPACE_MARGIN: u64 = 64 * 1024 * 1024;
+pub const WRITE_BUFFER_SIZE: usize = 4 * 1024 * 1024;
+pub const WRITE_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+pub const DEFAULT_S3_REGION: &str = "us-east-1";
+pub const DEFAULT_S3_PART_SIZE: u64 = 16 * 1024 * 1024;
+pub const MIN_S3_PART_SIZE: u64 = 5 * 1024 * 1024;
+pub const S3_SIGNED_HEADERS: &str = "host;x-amz-content-sha256;x-amz-date";
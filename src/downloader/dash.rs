@@ -0,0 +1,670 @@
+use anyhow::{anyhow, Result};
+use roxmltree::{Document, Node};
+use std::fmt;
+use url::Url;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrackKind {
+    Video,
+    Audio,
+    Other,
+}
+
+impl fmt::Display for TrackKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            TrackKind::Video => "video",
+            TrackKind::Audio => "audio",
+            TrackKind::Other => "other",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DashSegment {
+    pub url: String,
+    pub range: Option<(u64, u64)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Track {
+    pub kind: TrackKind,
+    pub bandwidth: u64,
+    pub mime_type: String,
+    pub segments: Vec<DashSegment>,
+    position: usize,
+}
+
+impl Track {
+    pub fn extension(&self) -> &'static str {
+        match (self.kind, self.mime_type.as_str()) {
+            (_, "video/webm") | (_, "audio/webm") => "webm",
+            (TrackKind::Audio, _) => "m4a",
+            (_, "text/vtt") => "vtt",
+            _ => "mp4",
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct SegmentTemplate {
+    media: Option<String>,
+    initialization: Option<String>,
+    start_number: Option<u64>,
+    timescale: Option<u64>,
+    duration: Option<u64>,
+    timeline: Option<Vec<(Option<u64>, u64, i64)>>,
+}
+
+impl SegmentTemplate {
+    fn from_node(node: Node) -> Self {
+        let timeline = child(node, "SegmentTimeline").map(|timeline| {
+            timeline
+                .children()
+                .filter(|n| n.has_tag_name("S"))
+                .map(|s| {
+                    (
+                        attribute_u64(s, "t"),
+                        attribute_u64(s, "d").unwrap_or(0),
+                        s.attribute("r").and_then(|r| r.parse().ok()).unwrap_or(0),
+                    )
+                })
+                .collect()
+        });
+        Self {
+            media: node.attribute("media").map(str::to_string),
+            initialization: node.attribute("initialization").map(str::to_string),
+            start_number: attribute_u64(node, "startNumber"),
+            timescale: attribute_u64(node, "timescale"),
+            duration: attribute_u64(node, "duration"),
+            timeline,
+        }
+    }
+
+    fn merge(&self, inner: &SegmentTemplate) -> SegmentTemplate {
+        SegmentTemplate {
+            media: inner.media.clone().or(self.media.clone()),
+            initialization: inner.initialization.clone().or(self.initialization.clone()),
+            start_number: inner.start_number.or(self.start_number),
+            timescale: inner.timescale.or(self.timescale),
+            duration: inner.duration.or(self.duration),
+            timeline: inner.timeline.clone().or(self.timeline.clone()),
+        }
+    }
+}
+
+struct Representation<'a> {
+    id: &'a str,
+    bandwidth: u64,
+    base_url: Url,
+    template: Option<SegmentTemplate>,
+    segment_list: Option<Node<'a, 'a>>,
+    node: Node<'a, 'a>,
+}
+
+pub fn parse_manifest(manifest: &str, manifest_url: &Url) -> Result<Vec<Track>> {
+    let document = Document::parse(manifest)?;
+    let mpd = document.root_element();
+    if !mpd.has_tag_name("MPD") {
+        return Err(anyhow!("Not a DASH manifest: {}", manifest_url));
+    }
+    if mpd.attribute("type") == Some("dynamic") {
+        return Err(anyhow!("Live DASH manifests are not supported"));
+    }
+
+    let presentation_duration = mpd
+        .attribute("mediaPresentationDuration")
+        .and_then(parse_duration);
+    let mpd_base_url = resolve_base_url(mpd, manifest_url);
+
+    let mut tracks: Vec<Track> = Vec::new();
+
+    for period in mpd.children().filter(|n| n.has_tag_name("Period")) {
+        let period_duration = period
+            .attribute("duration")
+            .and_then(parse_duration)
+            .or(presentation_duration);
+        let period_base_url = resolve_base_url(period, &mpd_base_url);
+        let period_template = child(period, "SegmentTemplate").map(SegmentTemplate::from_node);
+
+        for (index, adaptation_set) in period
+            .children()
+            .filter(|n| n.has_tag_name("AdaptationSet"))
+            .enumerate()
+        {
+            let Some(representation) =
+                select_representation(adaptation_set, &period_base_url, &period_template)
+            else {
+                continue;
+            };
+            let mime_type = representation
+                .node
+                .attribute("mimeType")
+                .or(adaptation_set.attribute("mimeType"))
+                .unwrap_or_default()
+                .to_string();
+            let kind = track_kind(adaptation_set, &mime_type);
+            let segments = build_segments(&representation, period_duration)?;
+
+            // Periods are concatenated, matching adaptation sets by kind and position.
+            match tracks
+                .iter_mut()
+                .find(|t| t.kind == kind && t.position == index)
+            {
+                Some(track) => track.segments.extend(segments),
+                None => tracks.push(Track {
+                    kind,
+                    bandwidth: representation.bandwidth,
+                    mime_type,
+                    segments,
+                    position: index,
+                }),
+            }
+        }
+    }
+
+    Ok(tracks)
+}
+
+pub fn select_tracks(tracks: Vec<Track>) -> Vec<Track> {
+    let best = |kind: TrackKind| {
+        tracks
+            .iter()
+            .filter(|t| t.kind == kind && !t.segments.is_empty())
+            .max_by_key(|t| t.bandwidth)
+            .cloned()
+    };
+    [best(TrackKind::Video), best(TrackKind::Audio)]
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+fn select_representation<'a>(
+    adaptation_set: Node<'a, 'a>,
+    period_base_url: &Url,
+    period_template: &Option<SegmentTemplate>,
+) -> Option<Representation<'a>> {
+    let adaptation_base_url = resolve_base_url(adaptation_set, period_base_url);
+    let adaptation_template = match (
+        period_template,
+        child(adaptation_set, "SegmentTemplate").map(SegmentTemplate::from_node),
+    ) {
+        (Some(outer), Some(inner)) => Some(outer.merge(&inner)),
+        (outer, inner) => inner.or(outer.clone()),
+    };
+
+    adaptation_set
+        .children()
+        .filter(|n| n.has_tag_name("Representation"))
+        .max_by_key(|n| attribute_u64(*n, "bandwidth").unwrap_or(0))
+        .map(|node| {
+            let template = match (
+                &adaptation_template,
+                child(node, "SegmentTemplate").map(SegmentTemplate::from_node),
+            ) {
+                (Some(outer), Some(inner)) => Some(outer.merge(&inner)),
+                (outer, inner) => inner.or(outer.clone()),
+            };
+            // A SegmentList on the adaptation set applies to every representation.
+            let segment_list = child(node, "SegmentList").or(child(adaptation_set, "SegmentList"));
+            Representation {
+                id: node.attribute("id").unwrap_or_default(),
+                bandwidth: attribute_u64(node, "bandwidth").unwrap_or(0),
+                base_url: resolve_base_url(node, &adaptation_base_url),
+                template,
+                segment_list,
+                node,
+            }
+        })
+}
+
+fn build_segments(
+    representation: &Representation,
+    period_duration: Option<f64>,
+) -> Result<Vec<DashSegment>> {
+    let base_url = &representation.base_url;
+
+    if let Some(segment_list) = representation.segment_list {
+        let mut segments = Vec::new();
+        if let Some(init) = child(segment_list, "Initialization") {
+            segments.push(DashSegment {
+                url: join(base_url, init.attribute("sourceURL"))?,
+                range: init.attribute("range").map(parse_range).transpose()?,
+            });
+        }
+        for segment_url in segment_list
+            .children()
+            .filter(|n| n.has_tag_name("SegmentURL"))
+        {
+            segments.push(DashSegment {
+                url: join(base_url, segment_url.attribute("media"))?,
+                range: segment_url
+                    .attribute("mediaRange")
+                    .map(parse_range)
+                    .transpose()?,
+            });
+        }
+        return Ok(segments);
+    }
+
+    if let Some(template) = &representation.template {
+        return build_template_segments(representation, template, period_duration);
+    }
+
+    // SegmentBase or a bare BaseURL: the representation is one addressable file.
+    Ok(vec![DashSegment {
+        url: base_url.to_string(),
+        range: None,
+    }])
+}
+
+fn build_template_segments(
+    representation: &Representation,
+    template: &SegmentTemplate,
+    period_duration: Option<f64>,
+) -> Result<Vec<DashSegment>> {
+    let base_url = &representation.base_url;
+    let mut segments = Vec::new();
+    let fill = |template: &str, number: u64, time: u64| {
+        fill_template(
+            template,
+            representation.id,
+            representation.bandwidth,
+            number,
+            time,
+        )
+    };
+
+    if let Some(initialization) = &template.initialization {
+        segments.push(DashSegment {
+            url: join(base_url, Some(&fill(initialization, 0, 0)))?,
+            range: None,
+        });
+    }
+
+    let media = template
+        .media
+        .as_ref()
+        .ok_or_else(|| anyhow!("SegmentTemplate without media attribute"))?;
+    let timescale = template.timescale.unwrap_or(1).max(1);
+    let mut number = template.start_number.unwrap_or(1);
+
+    if let Some(timeline) = &template.timeline {
+        let period_end = period_duration.map(|d| (d * timescale as f64) as u64);
+        let mut time = 0u64;
+        for (index, (start, duration, repeat)) in timeline.iter().enumerate() {
+            if let Some(start) = start {
+                time = *start;
+            }
+            if *duration == 0 {
+                continue;
+            }
+            let count = if *repeat < 0 {
+                // Repeat until the next S element or the end of the period.
+                let end = timeline
+                    .get(index + 1)
+                    .and_then(|(t, _, _)| *t)
+                    .or(period_end)
+                    .ok_or_else(|| anyhow!("Open-ended SegmentTimeline without duration"))?;
+                end.saturating_sub(time).div_ceil(*duration)
+            } else {
+                *repeat as u64 + 1
+            };
+            for _ in 0..count {
+                segments.push(DashSegment {
+                    url: join(base_url, Some(&fill(media, number, time)))?,
+                    range: None,
+                });
+                time += duration;
+                number += 1;
+            }
+        }
+    } else {
+        let duration = template
+            .duration
+            .ok_or_else(|| anyhow!("SegmentTemplate without duration or timeline"))?;
+        let period_duration =
+            period_duration.ok_or_else(|| anyhow!("Unknown DASH presentation duration"))?;
+        let segment_seconds = duration as f64 / timescale as f64;
+        let count = (period_duration / segment_seconds).ceil() as u64;
+        for index in 0..count {
+            segments.push(DashSegment {
+                url: join(base_url, Some(&fill(media, number, index * duration)))?,
+                range: None,
+            });
+            number += 1;
+        }
+    }
+
+    Ok(segments)
+}
+
+fn fill_template(template: &str, id: &str, bandwidth: u64, number: u64, time: u64) -> String {
+    let mut result = String::new();
+    let mut parts = template.split('$');
+    if let Some(first) = parts.next() {
+        result.push_str(first);
+    }
+    let mut in_identifier = true;
+    for part in parts {
+        if !in_identifier {
+            result.push_str(part);
+            in_identifier = true;
+            continue;
+        }
+        in_identifier = false;
+        if part.is_empty() {
+            // "$$" is an escaped dollar sign.
+            result.push('$');
+            continue;
+        }
+        let (name, format) = match part.split_once('%') {
+            Some((name, format)) => (name, Some(format)),
+            None => (part, None),
+        };
+        let value = match name {
+            "RepresentationID" => {
+                result.push_str(id);
+                continue;
+            }
+            "Number" => number,
+            "Time" => time,
+            "Bandwidth" => bandwidth,
+            _ => {
+                result.push('$');
+                result.push_str(part);
+                result.push('$');
+                continue;
+            }
+        };
+        let width = format
+            .and_then(|f| f.trim_start_matches('0').trim_end_matches('d').parse().ok())
+            .unwrap_or(0);
+        result.push_str(&format!("{:0width$}", value, width = width));
+    }
+    result
+}
+
+fn track_kind(adaptation_set: Node, mime_type: &str) -> TrackKind {
+    let content_type = adaptation_set.attribute("contentType").unwrap_or(mime_type);
+    if content_type.starts_with("video") {
+        TrackKind::Video
+    } else if content_type.starts_with("audio") {
+        TrackKind::Audio
+    } else {
+        TrackKind::Other
+    }
+}
+
+fn resolve_base_url(node: Node, parent: &Url) -> Url {
+    child(node, "BaseURL")
+        .and_then(|n| n.text())
+        .and_then(|text| parent.join(text.trim()).ok())
+        .unwrap_or_else(|| parent.clone())
+}
+
+fn join(base_url: &Url, path: Option<&str>) -> Result<String> {
+    match path {
+        Some(path) => Ok(base_url.join(path)?.to_string()),
+        None => Ok(base_url.to_string()),
+    }
+}
+
+fn child<'a>(node: Node<'a, 'a>, name: &str) -> Option<Node<'a, 'a>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+fn attribute_u64(node: Node, name: &str) -> Option<u64> {
+    node.attribute(name).and_then(|v| v.parse().ok())
+}
+
+// A broken range fails the manifest, falling back to the whole file would corrupt the output.
+fn parse_range(range: &str) -> Result<(u64, u64)> {
+    let invalid = || anyhow!("Invalid byte range in DASH manifest: {}", range);
+    let (start, end) = range.split_once('-').ok_or_else(invalid)?;
+    let start: u64 = start.trim().parse().map_err(|_| invalid())?;
+    let end: u64 = end.trim().parse().map_err(|_| invalid())?;
+    if end < start {
+        return Err(invalid());
+    }
+    Ok((start, end))
+}
+
+fn parse_duration(duration: &str) -> Option<f64> {
+    // ISO 8601 durations as used by MPD, e.g. "PT1H2M3.5S" or "P1DT2H".
+    let rest = duration.strip_prefix('P')?;
+    let mut seconds = 0f64;
+    let mut number = String::new();
+    let mut in_time = false;
+    for c in rest.chars() {
+        match c {
+            'T' => in_time = true,
+            '0'..='9' | '.' => number.push(c),
+            _ => {
+                let value: f64 = number.parse().ok()?;
+                number.clear();
+                seconds += value
+                    * match (c, in_time) {
+                        ('Y', false) => 365.0 * 86400.0,
+                        ('M', false) => 30.0 * 86400.0,
+                        ('W', false) => 7.0 * 86400.0,
+                        ('D', false) => 86400.0,
+                        ('H', true) => 3600.0,
+                        ('M', true) => 60.0,
+                        ('S', true) => 1.0,
+                        _ => return None,
+                    };
+            }
+        }
+    }
+    Some(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NUMBER_TEMPLATE: &str = r#"<?xml version="1.0"?>
+<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static" mediaPresentationDuration="PT10S">
+  <BaseURL>media/</BaseURL>
+  <Period>
+    <AdaptationSet mimeType="video/mp4">
+      <SegmentTemplate media="$RepresentationID$/seg-$Number%05d$.m4s"
+                       initialization="$RepresentationID$/init.mp4"
+                       startNumber="1" timescale="1000" duration="4000"/>
+      <Representation id="low" bandwidth="500000"/>
+      <Representation id="high" bandwidth="2000000"/>
+    </AdaptationSet>
+    <AdaptationSet mimeType="audio/mp4">
+      <SegmentTemplate media="audio/$Bandwidth$-$Number$.m4s" startNumber="0" duration="5"/>
+      <Representation id="aac" bandwidth="128000"/>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+
+    const TIME_TEMPLATE: &str = r#"<MPD type="static" mediaPresentationDuration="PT10S">
+  <Period>
+    <AdaptationSet contentType="video" mimeType="video/mp4">
+      <SegmentTemplate media="v/$Time$.m4s" timescale="10">
+        <SegmentTimeline>
+          <S t="0" d="20" r="2"/>
+          <S d="30"/>
+        </SegmentTimeline>
+      </SegmentTemplate>
+      <Representation id="v" bandwidth="1000"/>
+    </AdaptationSet>
+    <AdaptationSet contentType="audio" mimeType="audio/mp4">
+      <SegmentTemplate media="a/$Time$.m4s" timescale="10">
+        <SegmentTimeline>
+          <S t="0" d="20" r="-1"/>
+          <S t="60" d="15" r="-1"/>
+        </SegmentTimeline>
+      </SegmentTemplate>
+      <Representation id="a" bandwidth="100"/>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+
+    const SEGMENT_LIST: &str = r#"<MPD type="static" mediaPresentationDuration="PT4S">
+  <Period>
+    <AdaptationSet mimeType="video/mp4">
+      <Representation id="v" bandwidth="1000">
+        <BaseURL>video.mp4</BaseURL>
+        <SegmentList>
+          <Initialization range="0-99"/>
+          <SegmentURL mediaRange="100-1099"/>
+          <SegmentURL media="other.mp4" mediaRange="1100-1999"/>
+        </SegmentList>
+      </Representation>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+
+    const PERIODS: &str = r#"<MPD type="static">
+  <Period duration="PT4S">
+    <BaseURL>one/</BaseURL>
+    <SegmentTemplate media="$Number$.m4s" duration="2"/>
+    <AdaptationSet mimeType="video/mp4"><Representation id="v" bandwidth="1"/></AdaptationSet>
+    <AdaptationSet mimeType="audio/mp4"><Representation id="a" bandwidth="1"/></AdaptationSet>
+  </Period>
+  <Period duration="PT3S">
+    <BaseURL>two/</BaseURL>
+    <SegmentTemplate media="$Number$.m4s" duration="2"/>
+    <AdaptationSet mimeType="video/mp4"><Representation id="v" bandwidth="1"/></AdaptationSet>
+    <AdaptationSet mimeType="audio/mp4"><Representation id="a" bandwidth="1"/></AdaptationSet>
+  </Period>
+</MPD>"#;
+
+    fn parse(manifest: &str) -> Result<Vec<Track>> {
+        parse_manifest(
+            manifest,
+            &Url::parse("https://cdn.test/show/manifest.mpd").unwrap(),
+        )
+    }
+
+    fn urls(track: &Track) -> Vec<&str> {
+        track.segments.iter().map(|s| s.url.as_str()).collect()
+    }
+
+    #[test]
+    fn expands_number_templates() {
+        let tracks = parse(NUMBER_TEMPLATE).unwrap();
+        assert_eq!(tracks.len(), 2);
+
+        let video = &tracks[0];
+        assert_eq!(video.kind, TrackKind::Video);
+        assert_eq!(video.bandwidth, 2_000_000);
+        assert_eq!(
+            urls(video),
+            [
+                "https://cdn.test/show/media/high/init.mp4",
+                "https://cdn.test/show/media/high/seg-00001.m4s",
+                "https://cdn.test/show/media/high/seg-00002.m4s",
+                "https://cdn.test/show/media/high/seg-00003.m4s",
+            ]
+        );
+
+        let audio = &tracks[1];
+        assert_eq!(audio.kind, TrackKind::Audio);
+        assert_eq!(audio.extension(), "m4a");
+        assert_eq!(
+            urls(audio),
+            [
+                "https://cdn.test/show/media/audio/128000-0.m4s",
+                "https://cdn.test/show/media/audio/128000-1.m4s",
+            ]
+        );
+    }
+
+    #[test]
+    fn expands_segment_timelines() {
+        let tracks = parse(TIME_TEMPLATE).unwrap();
+
+        assert_eq!(
+            urls(&tracks[0]),
+            [
+                "https://cdn.test/show/v/0.m4s",
+                "https://cdn.test/show/v/20.m4s",
+                "https://cdn.test/show/v/40.m4s",
+                "https://cdn.test/show/v/60.m4s",
+            ]
+        );
+        // r="-1" repeats until the next S element, then until the end of the period.
+        assert_eq!(
+            urls(&tracks[1]),
+            [
+                "https://cdn.test/show/a/0.m4s",
+                "https://cdn.test/show/a/20.m4s",
+                "https://cdn.test/show/a/40.m4s",
+                "https://cdn.test/show/a/60.m4s",
+                "https://cdn.test/show/a/75.m4s",
+                "https://cdn.test/show/a/90.m4s",
+            ]
+        );
+
+        let open_ended = TIME_TEMPLATE.replace(r#" mediaPresentationDuration="PT10S""#, "");
+        assert!(parse(&open_ended).is_err());
+    }
+
+    #[test]
+    fn reads_segment_lists() {
+        let tracks = parse(SEGMENT_LIST).unwrap();
+        let segments: Vec<_> = tracks[0]
+            .segments
+            .iter()
+            .map(|s| (s.url.as_str(), s.range))
+            .collect();
+
+        assert_eq!(
+            segments,
+            [
+                ("https://cdn.test/show/video.mp4", Some((0, 99))),
+                ("https://cdn.test/show/video.mp4", Some((100, 1099))),
+                ("https://cdn.test/show/other.mp4", Some((1100, 1999))),
+            ]
+        );
+
+        assert!(parse(&SEGMENT_LIST.replace("1100-1999", "1999-1100")).is_err());
+        assert!(parse(&SEGMENT_LIST.replace("0-99", "0-")).is_err());
+    }
+
+    #[test]
+    fn concatenates_periods() {
+        let tracks = parse(PERIODS).unwrap();
+        assert_eq!(tracks.len(), 2);
+
+        for (track, name) in tracks.iter().zip(["video", "audio"]) {
+            assert_eq!(track.kind.to_string(), name);
+            assert_eq!(
+                urls(track),
+                [
+                    "https://cdn.test/show/one/1.m4s",
+                    "https://cdn.test/show/one/2.m4s",
+                    "https://cdn.test/show/two/1.m4s",
+                    "https://cdn.test/show/two/2.m4s",
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_live_manifests() {
+        let live = NUMBER_TEMPLATE.replace(r#"type="static""#, r#"type="dynamic""#);
+        assert!(parse(&live).is_err());
+        assert!(parse("<Playlist/>").is_err());
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("PT1H2M3.5S"), Some(3723.5));
+        assert_eq!(parse_duration("P1DT2H"), Some(93600.0));
+        assert_eq!(parse_duration("PT0.25S"), Some(0.25));
+        assert_eq!(parse_duration("P1M"), Some(30.0 * 86400.0));
+        assert_eq!(parse_duration("PT1M"), Some(60.0));
+        assert_eq!(parse_duration("1H"), None);
+        assert_eq!(parse_duration("PT5X"), None);
+        assert_eq!(parse_duration("PTS"), None);
+    }
+}
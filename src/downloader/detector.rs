@@ -0,0 +1,135 @@
+use super::constant;
+use std::fmt;
+use std::path::Path;
+use url::Url;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StreamKind {
+    Hls,
+    Dash,
+    Metalink,
+    Page,
+    File,
+}
+
+impl fmt::Display for StreamKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            StreamKind::Hls => "HLS",
+            StreamKind::Dash => "DASH",
+            StreamKind::Metalink => "Metalink",
+            StreamKind::Page => "Page",
+            StreamKind::File => "File",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+pub struct StreamDetector<'a> {
+    url: &'a Url,
+    content_type: Option<String>,
+}
+
+impl<'a> StreamDetector<'a> {
+    pub fn new(url: &'a Url, content_type: Option<&str>) -> Self {
+        // Drop parameters such as "; charset=utf-8" and normalize the case.
+        let content_type = content_type
+            .and_then(|ct| ct.split(';').next())
+            .map(|ct| ct.trim().to_ascii_lowercase())
+            .filter(|ct| !ct.is_empty());
+        Self { url, content_type }
+    }
+
+    pub fn detect(&self) -> Option<StreamKind> {
+        self.detect_by_content_type()
+            .or_else(|| self.detect_by_path())
+            .or_else(|| {
+                if self.needs_sniffing() {
+                    None
+                } else {
+                    Some(StreamKind::File)
+                }
+            })
+    }
+
+    pub fn needs_sniffing(&self) -> bool {
+        match &self.content_type {
+            Some(content_type) => {
+                constant::SNIFFABLE_CONTENT_TYPES.contains(&content_type.as_str())
+            }
+            None => true,
+        }
+    }
+
+    pub fn detect_by_content(&self, head: &[u8]) -> StreamKind {
+        let text = String::from_utf8_lossy(head);
+        let text = text.trim_start_matches('\u{feff}').trim_start();
+
+        if text.starts_with("#EXTM3U") {
+            return StreamKind::Hls;
+        }
+        if let Some(root) = xml_root_name(text) {
+            match root.to_ascii_lowercase().as_str() {
+                "mpd" => return StreamKind::Dash,
+                "metalink" => return StreamKind::Metalink,
+                "html" => return StreamKind::Page,
+                _ => {}
+            }
+        }
+        StreamKind::File
+    }
+
+    fn detect_by_content_type(&self) -> Option<StreamKind> {
+        let content_type = self.content_type.as_deref()?;
+        if constant::HLS_CONTENT_TYPES.contains(&content_type) {
+            Some(StreamKind::Hls)
+        } else if constant::DASH_CONTENT_TYPES.contains(&content_type) {
+            Some(StreamKind::Dash)
+        } else if constant::METALINK_CONTENT_TYPES.contains(&content_type) {
+            Some(StreamKind::Metalink)
+        } else if constant::PAGE_CONTENT_TYPES.contains(&content_type) {
+            Some(StreamKind::Page)
+        } else {
+            None
+        }
+    }
+
+    fn detect_by_path(&self) -> Option<StreamKind> {
+        let extension = Path::new(self.url.path())
+            .extension()?
+            .to_string_lossy()
+            .to_ascii_lowercase();
+        if constant::HLS_EXTENSIONS.contains(&extension.as_str()) {
+            Some(StreamKind::Hls)
+        } else if constant::DASH_EXTENSIONS.contains(&extension.as_str()) {
+            Some(StreamKind::Dash)
+        } else if constant::METALINK_EXTENSIONS.contains(&extension.as_str()) {
+            Some(StreamKind::Metalink)
+        } else {
+            None
+        }
+    }
+}
+
+fn xml_root_name(text: &str) -> Option<&str> {
+    let mut rest = text;
+    loop {
+        rest = rest.trim_start();
+        if rest.starts_with("<?") {
+            rest = &rest[rest.find("?>")? + 2..];
+        } else if rest.starts_with("<!--") {
+            rest = &rest[rest.find("-->")? + 3..];
+        } else if rest.starts_with("<!") {
+            rest = &rest[rest.find('>')? + 1..];
+        } else if let Some(tag) = rest.strip_prefix('<') {
+            let end = tag
+                .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+                .unwrap_or(tag.len());
+            let name = &tag[..end];
+            // Strip a namespace prefix such as "mpd:MPD".
+            return Some(name.rsplit(':').next().unwrap_or(name));
+        } else {
+            return None;
+        }
+    }
+}
@@ -1,8 +1,22 @@
+use crate::request::auth::Credential;
+use crate::request::proxy::ProxyConfig;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct DownloadInfo {
     pub url: String,
     pub headers: Option<HashMap<String, String>>,
+    pub out: Option<String>,
+    // A name suggested by the source, e.g. a page title, it fills the template like the name
+    // sent by the server. An out given by the job replaces the template instead.
+    #[serde(skip)]
+    pub filename: Option<String>,
+    pub dir: Option<String>,
+    pub sink: Option<String>,
+    pub proxy: Option<ProxyConfig>,
+    // Set-Cookie values for the job URL, kept in the shared cookie jar.
+    pub cookies: Option<Vec<String>>,
+    // Only sent to the host of the job URL unless a host is given.
+    pub auth: Option<Credential>,
 }
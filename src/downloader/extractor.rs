@@ -0,0 +1,64 @@
+mod generic;
+
+use crate::request::client::Client;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::Arc;
+use url::Url;
+
+pub use generic::GenericExtractor;
+
+#[derive(Clone, Debug)]
+pub struct Media {
+    pub url: String,
+    pub headers: HashMap<String, String>,
+    pub filename: Option<String>,
+}
+
+pub trait Extractor: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn extract<'a>(
+        &'a self,
+        client: &'a Client,
+        page_url: &'a Url,
+        html: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Media>>>;
+}
+
+pub struct ExtractorRegistry {
+    extractors: Vec<(Regex, Arc<dyn Extractor>)>,
+}
+
+impl ExtractorRegistry {
+    pub fn new() -> Self {
+        Self {
+            extractors: Vec::new(),
+        }
+    }
+
+    pub fn register(&mut self, pattern: &str, extractor: Arc<dyn Extractor>) -> Result<()> {
+        // Later registrations take precedence, so site extractors shadow the generic one.
+        self.extractors.insert(0, (Regex::new(pattern)?, extractor));
+        Ok(())
+    }
+
+    pub fn find(&self, page_url: &Url) -> Option<Arc<dyn Extractor>> {
+        self.extractors
+            .iter()
+            .find(|(pattern, _)| pattern.is_match(page_url.as_str()))
+            .map(|(_, extractor)| extractor.clone())
+    }
+}
+
+impl Default for ExtractorRegistry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry
+            .register(".*", Arc::new(GenericExtractor))
+            .expect("valid generic extractor pattern");
+        registry
+    }
+}
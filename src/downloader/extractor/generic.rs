@@ -0,0 +1,211 @@
+use super::{Extractor, Media};
+use crate::request::client::Client;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::Path;
+use url::Url;
+
+const VIDEO_META_PROPERTIES: [&str; 4] = [
+    "og:video",
+    "og:video:url",
+    "og:video:secure_url",
+    "twitter:player:stream",
+];
+
+pub struct GenericExtractor;
+
+impl Extractor for GenericExtractor {
+    fn name(&self) -> &'static str {
+        "generic"
+    }
+
+    fn extract<'a>(
+        &'a self,
+        _client: &'a Client,
+        page_url: &'a Url,
+        html: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Media>>> {
+        Box::pin(async move {
+            let mut candidates = Vec::new();
+
+            let media_tag = Regex::new(r"(?is)<(?:video|audio|source)\b[^>]*>")?;
+            for tag in media_tag.find_iter(html) {
+                candidates.extend(attribute(tag.as_str(), "src"));
+            }
+
+            let meta_tag = Regex::new(r"(?is)<meta\b[^>]*>")?;
+            for tag in meta_tag.find_iter(html) {
+                let property = attribute(tag.as_str(), "property")
+                    .or_else(|| attribute(tag.as_str(), "name"))
+                    .unwrap_or_default()
+                    .to_ascii_lowercase();
+                if VIDEO_META_PROPERTIES.contains(&property.as_str()) {
+                    candidates.extend(attribute(tag.as_str(), "content"));
+                }
+            }
+
+            // Players often build their sources in scripts, with JSON-escaped slashes.
+            let manifest = Regex::new(
+                r#"(?i)https?:(?:\\?/){2}[^"'\s<>]+?\.(?:m3u8|mpd)(?:\?(?:[^"'\s<>\\]|\\u0026|\\/)*)?"#,
+            )?;
+            for link in manifest.find_iter(html) {
+                candidates.push(link.as_str().replace("\\/", "/").replace("\\u0026", "&"));
+            }
+
+            let title = title(html);
+            let mut media: Vec<Media> = Vec::new();
+            for candidate in candidates {
+                if candidate.starts_with("blob:") || candidate.starts_with("data:") {
+                    continue;
+                }
+                let Ok(url) = page_url.join(candidate.trim()) else {
+                    continue;
+                };
+                if media.iter().any(|m| m.url == url.as_str()) {
+                    continue;
+                }
+
+                let filename = title.as_ref().map(|title| {
+                    let extension = Path::new(url.path())
+                        .extension()
+                        .map(|e| e.to_string_lossy().to_string())
+                        .unwrap_or_else(|| "mp4".to_string());
+                    match media.len() {
+                        0 => format!("{}.{}", title, extension),
+                        n => format!("{} ({}).{}", title, n, extension),
+                    }
+                });
+                let headers =
+                    HashMap::from([("Referer".to_string(), page_url.as_str().to_string())]);
+
+                media.push(Media {
+                    url: url.to_string(),
+                    headers,
+                    filename,
+                });
+            }
+
+            Ok(media)
+        })
+    }
+}
+
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let pattern = format!(r#"(?is)\s{}\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#, name);
+    let captures = Regex::new(&pattern).ok()?.captures(tag)?;
+    let value = captures
+        .get(1)
+        .or(captures.get(2))
+        .or(captures.get(3))?
+        .as_str();
+    Some(decode_entities(value)).filter(|v| !v.is_empty())
+}
+
+fn title(html: &str) -> Option<String> {
+    let title = Regex::new(r"(?is)<title[^>]*>(.*?)</title>")
+        .ok()?
+        .captures(html)?
+        .get(1)?
+        .as_str()
+        .trim()
+        .to_string();
+    // Keep the title usable as a single file name.
+    Some(decode_entities(&title).replace(['/', '\\'], "_")).filter(|t| !t.is_empty())
+}
+
+fn decode_entities(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::cookie_jar::CookieJar;
+    use crate::request::header_profile::HeaderProfile;
+    use crate::request::proxy::ProxyConfig;
+    use crate::request::tls::TlsConfig;
+    use std::sync::Arc;
+
+    const PAGE: &str = r#"<html><head>
+<title>Launch &amp; Landing</title>
+<meta property="og:video" content="https://cdn.test/og.mp4">
+<meta name="twitter:player:stream" content="/stream.webm">
+<meta property="og:image" content="https://cdn.test/poster.jpg">
+</head><body>
+<video src="/media/main.mp4" poster="poster.jpg">
+  <source src='/media/main.mp4' type='video/mp4'>
+  <source src=alt.webm type=video/webm>
+  <source src="blob:https://site.test/1234">
+</video>
+<audio src="data:audio/wav;base64,AAAA"></audio>
+<script>player.load({"hls":"https:\/\/cdn.test\/live\/index.m3u8?token=a\u0026b=c"});</script>
+</body></html>"#;
+
+    fn client() -> Client {
+        Client::new(
+            &ProxyConfig::default(),
+            &HeaderProfile::default(),
+            &TlsConfig::default(),
+            &Arc::new(CookieJar::default()),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn extracts_media_of_pages() {
+        let client = client();
+        let page_url = Url::parse("https://site.test/watch/42").unwrap();
+
+        let media = GenericExtractor
+            .extract(&client, &page_url, PAGE)
+            .await
+            .unwrap();
+
+        let found: Vec<(&str, Option<&str>)> = media
+            .iter()
+            .map(|m| (m.url.as_str(), m.filename.as_deref()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "https://site.test/media/main.mp4",
+                    Some("Launch & Landing.mp4")
+                ),
+                (
+                    "https://site.test/watch/alt.webm",
+                    Some("Launch & Landing (1).webm")
+                ),
+                ("https://cdn.test/og.mp4", Some("Launch & Landing (2).mp4")),
+                (
+                    "https://site.test/stream.webm",
+                    Some("Launch & Landing (3).webm")
+                ),
+                (
+                    "https://cdn.test/live/index.m3u8?token=a&b=c",
+                    Some("Launch & Landing (4).m3u8")
+                ),
+            ]
+        );
+        assert_eq!(media[0].headers["Referer"], page_url.as_str());
+    }
+
+    #[tokio::test]
+    async fn finds_nothing_in_plain_pages() {
+        let client = client();
+        let page_url = Url::parse("https://site.test/about").unwrap();
+
+        let media = GenericExtractor
+            .extract(&client, &page_url, "<html><p>No media here</p></html>")
+            .await
+            .unwrap();
+        assert!(media.is_empty());
+    }
+}
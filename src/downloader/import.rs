@@ -0,0 +1,140 @@
+use super::dto::DownloadInfo;
+use crate::request::auth::Credential;
+use crate::request::proxy::ProxyConfig;
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+// Parses a plain URL list or an aria2 input file, where indented "key=value" lines
+// hold the options of the URL above them.
+pub fn parse_input_file(input: &str) -> Result<Vec<DownloadInfo>> {
+    let mut infos: Vec<DownloadInfo> = Vec::new();
+
+    for (index, line) in input.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        if !line.starts_with([' ', '\t']) {
+            // aria2 lists mirrors of the same file tab separated, the first one is used.
+            let url = trimmed.split('\t').next().unwrap_or(trimmed).to_string();
+            infos.push(DownloadInfo {
+                url,
+                ..DownloadInfo::default()
+            });
+            continue;
+        }
+
+        let info = infos
+            .last_mut()
+            .ok_or_else(|| anyhow!("Line {}: option without a preceding URL", index + 1))?;
+        let (key, value) = trimmed
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Line {}: expected key=value, got {}", index + 1, trimmed))?;
+        let value = value.trim().to_string();
+
+        match key.trim() {
+            "out" => info.out = Some(value),
+            "dir" => info.dir = Some(value),
+            "all-proxy" => info.proxy.get_or_insert_with(ProxyConfig::default).url = Some(value),
+            "all-proxy-user" => {
+                info.proxy.get_or_insert_with(ProxyConfig::default).username = Some(value)
+            }
+            "all-proxy-passwd" => {
+                info.proxy.get_or_insert_with(ProxyConfig::default).password = Some(value)
+            }
+            "http-user" => info.auth.get_or_insert_with(Credential::default).username = Some(value),
+            "http-passwd" => {
+                info.auth.get_or_insert_with(Credential::default).password = Some(value)
+            }
+            "no-proxy" => {
+                info.proxy.get_or_insert_with(ProxyConfig::default).no_proxy = value
+                    .split(',')
+                    .map(|host| host.trim().to_string())
+                    .collect()
+            }
+            "header" => {
+                let (name, header_value) = value
+                    .split_once(':')
+                    .ok_or_else(|| anyhow!("Line {}: invalid header {}", index + 1, value))?;
+                info.headers
+                    .get_or_insert_with(HashMap::new)
+                    .insert(name.trim().to_lowercase(), header_value.trim().to_string());
+            }
+            other => eprintln!("Line {}: ignoring unsupported option {}", index + 1, other),
+        }
+    }
+
+    Ok(infos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_url_lists() {
+        let infos = parse_input_file(
+            "# exported links\nhttps://a.test/one.mp4\n\n  \nhttp://b.test/two.zip\r\n",
+        )
+        .unwrap();
+
+        let urls: Vec<&str> = infos.iter().map(|info| info.url.as_str()).collect();
+        assert_eq!(urls, ["https://a.test/one.mp4", "http://b.test/two.zip"]);
+        assert!(infos
+            .iter()
+            .all(|info| info.out.is_none() && info.headers.is_none()));
+    }
+
+    #[test]
+    fn parses_aria2_input_files() {
+        let input = "\
+https://a.test/one.mp4\thttps://mirror.test/one.mp4
+  out=first.mp4
+  dir=videos
+\theader=Referer: https://a.test/
+  header=X-Token:abc:def
+  all-proxy=http://proxy.test:3128
+  all-proxy-user=proxy-user
+  all-proxy-passwd=proxy-pass
+  no-proxy=localhost, .internal
+  http-user=user
+  http-passwd=pass
+  max-tries=5
+https://b.test/two.zip
+";
+        let infos = parse_input_file(input).unwrap();
+        assert_eq!(infos.len(), 2);
+
+        let first = &infos[0];
+        assert_eq!(first.url, "https://a.test/one.mp4");
+        assert_eq!(first.out.as_deref(), Some("first.mp4"));
+        assert_eq!(first.dir.as_deref(), Some("videos"));
+        let headers = first.headers.as_ref().unwrap();
+        assert_eq!(headers["referer"], "https://a.test/");
+        assert_eq!(headers["x-token"], "abc:def");
+        let proxy = first.proxy.as_ref().unwrap();
+        assert_eq!(proxy.url.as_deref(), Some("http://proxy.test:3128"));
+        assert_eq!(proxy.username.as_deref(), Some("proxy-user"));
+        assert_eq!(proxy.password.as_deref(), Some("proxy-pass"));
+        assert_eq!(proxy.no_proxy, ["localhost", ".internal"]);
+        let auth = first.auth.as_ref().unwrap();
+        assert_eq!(auth.username.as_deref(), Some("user"));
+        assert_eq!(auth.password.as_deref(), Some("pass"));
+        assert!(auth.host.is_none());
+
+        assert_eq!(infos[1].url, "https://b.test/two.zip");
+        assert!(infos[1].out.is_none() && infos[1].proxy.is_none());
+    }
+
+    #[test]
+    fn rejects_broken_options() {
+        for input in [
+            "  out=orphan.mp4\nhttps://a.test/",
+            "https://a.test/\n  out",
+            "https://a.test/\n  header=no colon",
+        ] {
+            assert!(parse_input_file(input).is_err(), "{:?}", input);
+        }
+    }
+}
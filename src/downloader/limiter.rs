@@ -0,0 +1,39 @@
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::{sleep, Instant};
+
+// Token bucket shared by all segments of a job, allowing a burst of one second.
+#[derive(Debug)]
+pub struct RateLimiter {
+    bytes_per_second: f64,
+    state: Mutex<(Instant, f64)>,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_second: u64) -> Self {
+        let bytes_per_second = bytes_per_second.max(1) as f64;
+        Self {
+            bytes_per_second,
+            state: Mutex::new((Instant::now(), bytes_per_second)),
+        }
+    }
+
+    pub async fn acquire(&self, bytes: u64) {
+        let wait = {
+            let mut state = self.state.lock().await;
+            let (last, available) = &mut *state;
+            let now = Instant::now();
+            let refill = now.duration_since(*last).as_secs_f64() * self.bytes_per_second;
+            *available = (*available + refill).min(self.bytes_per_second) - bytes as f64;
+            *last = now;
+            if *available < 0.0 {
+                Duration::from_secs_f64(-*available / self.bytes_per_second)
+            } else {
+                Duration::ZERO
+            }
+        };
+        if !wait.is_zero() {
+            sleep(wait).await;
+        }
+    }
+}
@@ -1,148 +1,239 @@
+use super::category::CategoryRule;
+use super::constant;
+use super::detector::{StreamDetector, StreamKind};
+use super::dto::DownloadInfo;
+use super::limiter::RateLimiter;
+use super::output::OutputResolver;
 use super::progress::{ProgressBar, ProgressManager};
 use super::segment::Segment;
-use crate::request::{client::Client, response::Response, user_agent::UserAgent};
+use super::sink::SinkTarget;
+use super::storage::{FileStorage, Storage};
+use super::strategy::{Probe, SharedRegistry};
+use super::template::TemplateContext;
+use super::upload::S3Uploader;
+use super::writer::{FsyncPolicy, Writer, WriterOptions};
+use crate::request::{
+    client::Client, content_disposition, response::Response, tor::CircuitRenewal,
+};
 use anyhow::{anyhow, Result};
 use bytes::Bytes;
 use futures_core::Stream;
-use std::cmp::min;
+use percent_encoding::percent_decode_str;
 use std::collections::HashMap;
 use std::fmt;
-use std::fs::File;
-use std::fs::OpenOptions;
-use std::path::{Path, PathBuf};
+use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::{RwLock, Semaphore};
 use tokio_stream::StreamExt;
+use tokio_util::sync::CancellationToken;
 use url::Url;
 
-#[cfg(feature = "unix")]
-use std::os::unix::fs::FileExt;
-
-#[cfg(feature = "windows")]
-use std::os::windows::fs::FileExt;
-
 #[derive(Clone, Debug)]
 pub struct Downloader {
     client: Client,
     segment_size: u64,
     max_concurrent: usize,
+    registry: SharedRegistry,
+    output: OutputResolver,
+    categories: Arc<Vec<CategoryRule>>,
+    limiter: Option<Arc<RateLimiter>>,
+    preallocate: bool,
+    writer_options: WriterOptions,
+    sinks: Arc<HashMap<String, SinkTarget>>,
+    uploader: Option<Arc<S3Uploader>>,
+    renewal: Option<Arc<CircuitRenewal>>,
+    depth: usize,
 }
 
 impl Downloader {
     pub fn new(
-        use_tor: bool,
-        user_agent: &UserAgent,
+        client: Client,
         segment_size: u64,
         max_concurrent: usize,
+        download_dir: &str,
+        filename_template: &str,
+        registry: SharedRegistry,
     ) -> Self {
         Self {
-            client: Client::new(use_tor, user_agent).unwrap(),
+            client,
             segment_size,
             max_concurrent,
+            registry,
+            output: OutputResolver::new(download_dir, filename_template),
+            categories: Arc::new(Vec::new()),
+            limiter: None,
+            preallocate: false,
+            writer_options: WriterOptions::default(),
+            sinks: Arc::new(HashMap::new()),
+            uploader: None,
+            renewal: None,
+            depth: 0,
         }
     }
 
-    pub async fn download_file(
-        &self,
-        url: &str,
-        headers: Option<&HashMap<String, String>>,
-    ) -> Result<()> {
-        let head_response = self.client.head(url, headers).await?;
-        let content_type = head_response.content_type();
-        let filename = self.get_filename(&head_response, url);
-        let progress_manager = Arc::new(ProgressManager::new(filename.clone()));
+    pub fn with_preallocate(mut self, preallocate: bool) -> Self {
+        self.preallocate = preallocate;
+        self
+    }
 
-        if url.ends_with(".m3u8")
-            || content_type
-                .map(|ct| ct.contains("application/vnd.apple.mpegurl"))
-                .unwrap_or(false)
-        {
-            // ts playlist
-            let filename = if filename.ends_with(".m3u8") {
-                let ts_path = Path::new(&filename).with_extension("ts");
-                ts_path.to_string_lossy().to_string()
-            } else {
-                filename.to_string()
+    pub fn with_writer_options(mut self, writer_options: WriterOptions) -> Self {
+        self.writer_options = writer_options;
+        self
+    }
+
+    pub fn with_sinks(mut self, sinks: HashMap<String, SinkTarget>) -> Self {
+        self.sinks = Arc::new(sinks);
+        self
+    }
+
+    pub fn with_uploader(mut self, uploader: Option<S3Uploader>) -> Self {
+        self.uploader = uploader.map(Arc::new);
+        self
+    }
+
+    pub fn with_circuit_renewal(mut self, renewal: Option<CircuitRenewal>) -> Self {
+        self.renewal = renewal.map(Arc::new);
+        self
+    }
+
+    pub fn with_categories(mut self, categories: Vec<CategoryRule>) -> Self {
+        self.categories = Arc::new(categories);
+        self
+    }
+
+    pub async fn download(&self, info: &DownloadInfo) -> Result<()> {
+        let probe = self.probe_info(info).await?;
+        self.download_probe(probe).await
+    }
+
+    pub(super) async fn download_media(&self, info: &DownloadInfo) -> Result<()> {
+        let probe = self.probe_info(info).await?;
+        if probe.kind == StreamKind::Page {
+            return Err(anyhow!("Extracted URL is not media: {}", info.url));
+        }
+        self.download_probe(probe).await
+    }
+
+    // Saves whatever the URL returns, e.g. a page without media.
+    pub(super) async fn download_file(&self, probe: &Probe) -> Result<()> {
+        self.download_probe(Probe {
+            kind: StreamKind::File,
+            ..probe.clone()
+        })
+        .await
+    }
+
+    // Pages and metalinks start downloads of their own, which must not nest without end.
+    pub(super) fn nested(&self) -> Result<Self> {
+        if self.depth >= constant::MAX_NESTING_DEPTH {
+            return Err(anyhow!(
+                "Downloads nest deeper than {} levels",
+                constant::MAX_NESTING_DEPTH
+            ));
+        }
+        let mut downloader = self.clone();
+        downloader.depth += 1;
+        Ok(downloader)
+    }
+
+    async fn probe_info(&self, info: &DownloadInfo) -> Result<Probe> {
+        let mut probe = self.probe(&info.url, info.headers.as_ref()).await?;
+        if let Some(filename) = &info.filename {
+            probe.filename = filename.clone();
+        }
+        if let Some(out) = &info.out {
+            // Like the config template, directories in the name are created, so they are
+            // rendered and sanitized along with the directory while reserving the output.
+            let (out_dir, name) = match out.rsplit_once('/') {
+                Some((out_dir, name)) => (Some(out_dir.to_string()), name),
+                None => (None, out.as_str()),
             };
-            progress_manager
-                .main_progress_bar
-                .write()
-                .await
-                .set_name(filename.clone());
+            probe.filename = TemplateContext::new(&info.url, &probe.filename).render(name);
+            probe.out_dir = out_dir;
+            probe.explicit_name = true;
+        }
+        probe.dir = info.dir.clone();
+        probe.sink = info.sink.clone();
+        Ok(probe)
+    }
 
-            let get_response = self.client.get(url, headers).await?;
-            let base_url = Url::parse(url)?;
-            let playlist: Vec<String> = get_response
-                .text()
-                .await
-                .unwrap_or_default()
-                .lines()
-                .filter_map(|line| {
-                    if (!line.starts_with("#")) && line.contains(".") {
-                        let ts_url = if let Ok(absolute_url) = Url::parse(line) {
-                            Some(absolute_url.to_string())
-                        } else {
-                            base_url.join(line).ok().map(|u| u.to_string())
-                        };
-                        if let Some(url) = ts_url {
-                            return Some(url);
-                        }
-                    }
-                    None
-                })
-                .collect();
+    async fn download_probe(&self, mut probe: Probe) -> Result<()> {
+        // The first matching category rule applies, a directory given by the job wins.
+        let mut downloader = match self.categories.iter().find(|rule| rule.matches(&probe)) {
+            Some(rule) => {
+                if probe.dir.is_none() {
+                    probe.dir = rule.dir.clone();
+                }
+                self.for_category(rule)
+            }
+            None => self.clone(),
+        };
+        // An output named by the job replaces the template instead of filling it.
+        if probe.explicit_name {
+            downloader.output = downloader
+                .output
+                .with_template(constant::EXPLICIT_NAME_TEMPLATE);
+        }
 
-            let segments = self.get_segments_info(playlist).await?;
-            let total_size: u64 = segments.iter().map(|s| s.end - s.start + 1).sum();
+        let strategy = self
+            .registry
+            .find(&probe)
+            .ok_or_else(|| anyhow!("No download strategy for {}", probe.url))?;
 
+        let plans = strategy.plan(&downloader, &probe).await?;
+        let sink = match (&probe.sink, plans.as_slice()) {
+            // Strategies without plans delegate to other jobs, which stream on their own.
+            (Some(name), [_, ..]) => Some(downloader.sink(name)?),
+            _ => None,
+        };
+        match (&sink, plans.len()) {
+            (None, _) => downloader
+                .output
+                .check_free_space(strategy.required_space(&plans))?,
+            (Some(_), 1) => {}
+            (Some(_), count) => {
+                return Err(anyhow!(
+                    "{} has {} outputs, only a single one can be streamed",
+                    probe.url,
+                    count
+                ))
+            }
+        }
+        let progress_manager = Arc::new(ProgressManager::new(probe.filename.clone()));
+        if let [plan] = plans.as_slice() {
             progress_manager
                 .main_progress_bar
-                .read()
+                .write()
                 .await
-                .set_length(total_size);
-
-            self.download_parallel(
-                segments,
-                headers,
-                &filename,
-                false,
-                progress_manager.clone(),
-            )
-            .await?;
-        } else {
-            // normal file
-            let content_length = head_response.content_length();
-            let accept_ranges = head_response.accept_ranges();
-
-            match (content_length, accept_ranges) {
-                (Some(content_length), Some(accept_ranges)) if accept_ranges == "bytes" => {
-                    progress_manager
-                        .main_progress_bar
-                        .read()
-                        .await
-                        .set_length(content_length);
-
-                    let url_arc = Arc::new(url.to_string());
-                    let mut segments = Vec::new();
-
-                    for offset in (0..content_length).step_by(self.segment_size as usize) {
-                        let end = min(offset + self.segment_size - 1, content_length - 1);
-                        let segment = Segment::new(url_arc.clone(), offset, end);
-                        segments.push(segment);
-                    }
-                    self.download_parallel(
-                        segments,
-                        headers,
-                        &filename,
-                        true,
+                .set_name(plan.filename.clone());
+        }
+        let total_size: u64 = plans.iter().map(|plan| plan.size()).sum();
+        progress_manager
+            .main_progress_bar
+            .read()
+            .await
+            .set_length(total_size);
+
+        match (sink, plans.as_slice()) {
+            (Some(sink), [plan]) => {
+                let sink = sink.open().await?;
+                let result = strategy
+                    .stream(
+                        &downloader,
+                        &probe,
+                        plan,
+                        sink.storage(),
                         progress_manager.clone(),
                     )
+                    .await;
+                let finished = sink.finish().await;
+                result.and(finished)?;
+            }
+            _ => {
+                strategy
+                    .fetch(&downloader, &probe, &plans, progress_manager.clone())
                     .await?;
-                }
-                _ => {
-                    self.download_full(url, &filename, progress_manager.clone())
-                        .await?
-                }
+                strategy.finalize(&downloader, &probe, &plans).await?;
             }
         }
 
@@ -151,53 +242,154 @@ impl Downloader {
         Ok(())
     }
 
-    fn get_filename(&self, response: &Response, url: &str) -> String {
-        let filename = if let Some(content_disposition) = response.content_disposition() {
-            if let Some(filename) = content_disposition.split("filename=").nth(1) {
-                return filename.trim_matches('"').to_string();
-            } else {
-                "downloaded_file.ts".to_string()
-            }
-        } else if let Ok(parsed_url) = Url::parse(url) {
-            if let Some(filename) = Path::new(parsed_url.path()).file_name() {
-                return filename.to_string_lossy().to_string();
-            } else {
-                "downloaded_file.ts".to_string()
-            }
-        } else {
-            "downloaded_file.ts".to_string()
+    pub(super) async fn upload(&self, path: &Path) -> Result<()> {
+        let Some(uploader) = &self.uploader else {
+            return Ok(());
         };
+        let key = self.output.relative_key(path)?;
+        uploader.upload(path, &key).await?;
+        if uploader.remove_local() {
+            tokio::fs::remove_file(path).await?;
+        }
+        Ok(())
+    }
 
-        let mut path = PathBuf::from(&filename);
-        let parent = Path::new("../files");
+    fn sink(&self, name: &str) -> Result<SinkTarget> {
+        match self.sinks.get(name) {
+            Some(target) => Ok(target.clone()),
+            None if name == "stdout" => Ok(SinkTarget::Stdout),
+            None => Err(anyhow!("Unknown output sink: {}", name)),
+        }
+    }
 
-        let mut count = 1;
-        while path.exists() {
-            let file_stem = path.file_stem().unwrap_or_default().to_string_lossy();
-            let extension = path.extension().unwrap_or_default().to_string_lossy();
-            let new_filename = if extension.is_empty() {
-                format!("{} ({})", file_stem, count)
-            } else {
-                format!("{} ({}).{}", file_stem, count, extension)
-            };
+    fn for_category(&self, rule: &CategoryRule) -> Self {
+        let mut downloader = self.clone();
+        if let Some(max_concurrent) = rule.max_concurrent_count {
+            downloader.max_concurrent = max_concurrent.max(1);
+        }
+        if let Some(template) = &rule.filename_template {
+            downloader.output = self.output.with_template(template);
+        }
+        if let Some(speed_limit) = rule.speed_limit {
+            downloader.limiter = Some(Arc::new(RateLimiter::new(speed_limit)));
+        }
+        downloader
+    }
 
-            path = parent.join(&new_filename);
-            count += 1;
+    async fn throttle(&self, bytes: u64) {
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire(bytes).await;
         }
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub fn output(&self) -> &OutputResolver {
+        &self.output
+    }
 
-        path.file_name().unwrap().to_string_lossy().to_string()
+    pub fn fsync_policy(&self) -> FsyncPolicy {
+        self.writer_options.fsync_policy
     }
 
-    async fn get_segments_info(&self, urls: Vec<String>) -> Result<Vec<Segment>> {
+    pub fn segment_size(&self) -> u64 {
+        self.segment_size
+    }
+
+    async fn probe(&self, url: &str, headers: Option<&HashMap<String, String>>) -> Result<Probe> {
+        let head_response = self.client.head(url, headers).await?;
+        let kind = self
+            .detect_stream_kind(url, &head_response, headers)
+            .await?;
+
+        Ok(Probe {
+            url: url.to_string(),
+            headers: headers.cloned(),
+            kind,
+            filename: self.get_filename(&head_response, url),
+            content_length: head_response.content_length(),
+            accept_ranges: head_response.accept_ranges().as_deref() == Some("bytes"),
+            content_type: head_response.content_type(),
+            dir: None,
+            out_dir: None,
+            explicit_name: false,
+            sink: None,
+        })
+    }
+
+    async fn detect_stream_kind(
+        &self,
+        url: &str,
+        head_response: &Response,
+        headers: Option<&HashMap<String, String>>,
+    ) -> Result<StreamKind> {
+        let parsed_url = Url::parse(url)?;
+        let content_type = head_response.content_type();
+        let detector = StreamDetector::new(&parsed_url, content_type.as_deref());
+
+        if let Some(kind) = detector.detect() {
+            return Ok(kind);
+        }
+
+        let mut headers = headers.cloned().unwrap_or_default();
+        headers.insert(
+            "Range".to_string(),
+            format!("bytes=0-{}", constant::SNIFF_LENGTH - 1),
+        );
+        let response = self.client.get(url, Some(&headers)).await?;
+        let mut stream = response.bytes_stream();
+        let mut head = Vec::new();
+
+        // Servers that ignore the Range header are cut off once enough bytes arrived.
+        while head.len() < constant::SNIFF_LENGTH {
+            match stream.next().await {
+                Some(Ok(chunk)) => head.extend_from_slice(&chunk),
+                _ => break,
+            }
+        }
+        head.truncate(constant::SNIFF_LENGTH);
+
+        Ok(detector.detect_by_content(&head))
+    }
+
+    fn get_filename(&self, response: &Response, url: &str) -> String {
+        response
+            .content_disposition()
+            .and_then(|value| content_disposition::parse_filename(&value))
+            .or_else(|| {
+                let parsed_url = Url::parse(url).ok()?;
+                let segment = parsed_url.path_segments()?.next_back()?.to_string();
+                let decoded = percent_decode_str(&segment).decode_utf8_lossy();
+                Some(decoded.to_string()).filter(|s| !s.is_empty())
+            })
+            .unwrap_or_else(|| "downloaded_file.ts".to_string())
+    }
+
+    pub(super) async fn get_segments_info(
+        &self,
+        sources: Vec<(String, Option<(u64, u64)>)>,
+    ) -> Result<Vec<Segment>> {
+        if let Some((url, (start, end))) = sources
+            .iter()
+            .find_map(|(url, range)| range.filter(|(start, end)| end < start).map(|r| (url, r)))
+        {
+            return Err(anyhow!("Invalid byte range {}-{} for {}", start, end, url));
+        }
+
         let semaphore = Arc::new(Semaphore::new(self.max_concurrent));
         let mut handles = Vec::new();
 
-        for url in urls.iter().cloned() {
+        for (url, range) in sources.iter().cloned() {
             let client = self.client.clone();
             let permit = semaphore.clone().acquire_owned().await?;
 
             let handle = tokio::spawn(async move {
                 let _permit = permit;
+                if let Some((start, end)) = range {
+                    return Some(end - start + 1);
+                }
                 match client.head(&url, None).await {
                     Ok(response) => response.content_length(),
                     Err(_) => None,
@@ -209,19 +401,19 @@ impl Downloader {
         let mut segments = Vec::new();
         let mut start = 0u64;
 
-        for (handle, url) in handles.into_iter().zip(urls.iter()) {
+        for (handle, (url, range)) in handles.into_iter().zip(sources.iter()) {
+            // A missing segment would leave a truncated file behind.
             let size = match handle.await {
                 Ok(Some(size)) => size,
-                Ok(None) => {
-                    eprintln!("Fail to get size {}", url);
-                    continue;
-                }
-                Err(e) => {
-                    eprintln!("Fail to get size {}, caused {}", url, e);
-                    continue;
-                }
+                Ok(None) => return Err(anyhow!("Fail to get size {}", url)),
+                Err(e) => return Err(anyhow!("Fail to get size {}, caused {}", url, e)),
             };
-            let segment = Segment::new(Arc::from(url.clone()), start, start + size - 1);
+            // An empty segment adds nothing to the file and has no byte range.
+            if size == 0 {
+                continue;
+            }
+            let segment = Segment::new(Arc::from(url.clone()), start, start + size - 1)
+                .with_source_range(*range);
             segments.push(segment);
             start += size;
         }
@@ -229,27 +421,59 @@ impl Downloader {
         Ok(segments)
     }
 
-    async fn download_parallel(
+    pub(super) async fn download_parallel(
         &self,
-        segments: Vec<Segment>,
+        segments: &[Segment],
         default_headers: Option<&HashMap<String, String>>,
-        output_path: &str,
+        output_path: &Path,
         accept_ranges: bool,
         progress_manager: Arc<ProgressManager>,
     ) -> Result<()> {
-        let file = Arc::new(
-            OpenOptions::new()
-                .create(true)
-                .write(true)
-                .open("files/".to_owned() + output_path)?,
-        );
+        let storage = Arc::new(FileStorage::open(output_path)?);
+        self.download_segments(
+            segments,
+            default_headers,
+            storage,
+            accept_ranges,
+            progress_manager,
+        )
+        .await
+    }
+
+    pub(super) async fn download_segments(
+        &self,
+        segments: &[Segment],
+        default_headers: Option<&HashMap<String, String>>,
+        storage: Arc<dyn Storage>,
+        accept_ranges: bool,
+        progress_manager: Arc<ProgressManager>,
+    ) -> Result<()> {
+        let size = segments.iter().map(|s| s.end + 1).max().unwrap_or(0);
+        if self.preallocate && size > 0 {
+            storage.allocate(size)?;
+        }
+        let mut position = storage.position();
+        let writer = Arc::new(Writer::new(storage, &self.writer_options));
         let semaphore = Arc::new(Semaphore::new(self.max_concurrent));
+        let failed = CancellationToken::new();
 
         let mut handles = vec![];
 
         for (index, segment) in segments.iter().enumerate() {
+            // Data ahead of an ordered storage is held in memory, so a segment only starts
+            // within the memory budget. The segment at the position always starts.
+            if let Some(position) = position.as_mut() {
+                let budget = self.writer_options.memory_budget as u64;
+                tokio::select! {
+                    waited = position.wait_for(|position| segment.start <= position + budget) => {
+                        waited?;
+                    }
+                    // The position never moves past a failed segment.
+                    _ = failed.cancelled() => break,
+                }
+            }
             let self_clone = self.clone();
-            let client = self.client.clone();
+            let client = self.client.circuit(index);
             let segment = segment.clone();
             let total = segments.len();
             let default_headers = default_headers.cloned();
@@ -259,46 +483,51 @@ impl Downloader {
                 format!("{}/{}", index + 1, total),
             );
             let permit = semaphore.clone().acquire_owned().await?;
-            let file = Arc::clone(&file);
+            let writer = Arc::clone(&writer);
+            let failed = failed.clone();
 
             let handle = tokio::spawn(async move {
                 let _permit = permit;
-                let mut headers = if default_headers.is_some() {
-                    default_headers.unwrap().clone()
-                } else {
-                    HashMap::<String, String>::new()
-                };
-                if accept_ranges {
+                let mut headers = default_headers.unwrap_or_default();
+                if accept_ranges || segment.source_range.is_some() {
                     headers.insert("Range".to_string(), segment.get_range_header());
                 }
 
-                self_clone
+                let result = self_clone
                     .retryable_get_segment(
                         &client,
-                        &file,
+                        &writer,
                         &segment,
                         &headers,
                         main_progress_bar,
                         progress_bar,
                     )
-                    .await
-                    .ok()
+                    .await;
+                if result.is_err() {
+                    failed.cancel();
+                }
+                result
             });
 
             handles.push(handle);
         }
 
+        // Every segment is awaited, then the first failure fails the whole download.
+        let mut result = Ok(());
         for handle in handles {
-            handle.await?;
+            let segment_result = handle.await.map_err(anyhow::Error::from).and_then(|r| r);
+            if result.is_ok() {
+                result = segment_result;
+            }
         }
 
-        Ok(())
+        result
     }
 
     async fn retryable_get_segment(
         &self,
         client: &Client,
-        file: &Arc<File>,
+        writer: &Writer,
         segment: &Segment,
         headers: &HashMap<String, String>,
         global_progress_bar: Arc<RwLock<ProgressBar>>,
@@ -311,7 +540,7 @@ impl Downloader {
             match self
                 .get_segment(
                     client,
-                    file,
+                    writer,
                     segment,
                     headers,
                     global_progress_bar.clone(),
@@ -326,6 +555,15 @@ impl Downloader {
                         e, &segment.start, &segment.end, attempts, max_retries
                     );
                     attempts += 1;
+                    let renewed = match &self.renewal {
+                        Some(renewal) => renewal.record_failure().await,
+                        None => false,
+                    };
+                    if renewed {
+                        if let Err(e) = self.client.renew_circuits() {
+                            eprintln!("Failed to rebuild clients for new Tor circuits: {}", e);
+                        }
+                    }
 
                     tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
                 }
@@ -343,90 +581,132 @@ impl Downloader {
     async fn get_segment(
         &self,
         client: &Client,
-        file: &Arc<File>,
+        writer: &Writer,
         segment: &Segment,
         headers: &HashMap<String, String>,
         global_progress_bar: Arc<RwLock<ProgressBar>>,
         local_progress_bar: &ProgressBar,
     ) -> Result<()> {
-        match client.get(&segment.url, Some(&headers)).await {
-            Ok(response) => {
-                self.write_segment(
-                    response.bytes_stream(),
-                    file,
-                    segment.start,
-                    global_progress_bar,
-                    local_progress_bar,
-                )
-                .await?;
-                Ok(())
-            }
-            Err(e) => Err(anyhow!(
+        let response = client.get(&segment.url, Some(headers)).await.map_err(|e| {
+            anyhow!(
                 "Failed to download segment {}: {}, Chunk {}-{}",
                 segment.url,
                 e,
                 segment.start,
                 segment.end,
-            )),
+            )
+        })?;
+        // An error page must never end up inside the file.
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Segment {} answered with {}",
+                segment.url,
+                response.status()
+            ));
         }
+        self.write_segment(
+            response.bytes_stream(),
+            writer,
+            segment,
+            global_progress_bar,
+            local_progress_bar,
+        )
+        .await
     }
 
+    // Every segment must write exactly its own length, the file length says nothing once
+    // the file was preallocated.
     async fn write_segment(
         &self,
         mut stream: impl Stream<Item = Result<Bytes>> + Unpin,
-        file: &Arc<File>,
-        start: u64,
+        writer: &Writer,
+        segment: &Segment,
         global_progress_bar: Arc<RwLock<ProgressBar>>,
         local_progress_bar: &ProgressBar,
     ) -> Result<()> {
-        // let mut file = file.lock().await;
-        let mut offset = start;
-        // file.seek(SeekFrom::Start(offset)).await?;
+        let length = segment.end - segment.start + 1;
+        let mut segment_writer = writer.segment(segment.start);
+        let mut written = 0u64;
 
         while let Some(chunk) = stream.next().await {
             if let Ok(chunk) = chunk {
-                file.write_at(&chunk, offset)?;
                 let len = chunk.len() as u64;
+                if written + len > length {
+                    return Err(anyhow!(
+                        "Segment {}-{} received more than {} bytes",
+                        segment.start,
+                        segment.end,
+                        length
+                    ));
+                }
+                segment_writer.write(&chunk)?;
+                self.throttle(len).await;
                 local_progress_bar.increase(len);
                 global_progress_bar.read().await.increase(len);
-                offset += len;
+                written += len;
             } else {
-                return Err(anyhow!("Failed to download segment {}", offset));
+                return Err(anyhow!(
+                    "Failed to download segment {}",
+                    segment.start + written
+                ));
             }
         }
 
-        file.sync_all()?;
+        if written != length {
+            return Err(anyhow!(
+                "Segment {}-{} is incomplete: expected {} bytes, got {}",
+                segment.start,
+                segment.end,
+                length,
+                written
+            ));
+        }
+        segment_writer.finish()?;
         local_progress_bar.finish_and_clear();
         Ok(())
     }
 
-    async fn download_full(
+    pub(super) async fn download_full(
         &self,
         url: &str,
-        output_path: &str,
+        expected_size: Option<u64>,
+        storage: Arc<dyn Storage>,
         progress_manager: Arc<ProgressManager>,
     ) -> Result<()> {
         let response = self.client.get(url, None).await?;
-        let file = File::create(output_path)?;
+        if !response.status().is_success() {
+            return Err(anyhow!("{} answered with {}", url, response.status()));
+        }
+        let writer = Writer::new(storage, &self.writer_options);
+        let mut segment_writer = writer.segment(0);
         let mut stream = response.bytes_stream();
-        let mut offset = 0u64;
+        let mut written = 0u64;
 
         while let Some(chunk) = stream.next().await {
             if let Ok(chunk) = chunk {
-                file.write_at(&chunk, offset)?;
+                segment_writer.write(&chunk)?;
                 let len = chunk.len() as u64;
+                self.throttle(len).await;
                 progress_manager
                     .main_progress_bar
                     .read()
                     .await
                     .increase(len);
-                offset += len;
+                written += len;
             } else {
-                return Err(anyhow!("Failed to write file: {}", output_path));
+                return Err(anyhow!("Failed to download {}", url));
             }
         }
 
-        file.sync_all()?;
+        if let Some(expected_size) = expected_size.filter(|size| *size != written) {
+            return Err(anyhow!(
+                "Size mismatch for {}: expected {} bytes, got {}",
+                url,
+                expected_size,
+                written
+            ));
+        }
+        segment_writer.finish()?;
 
         Ok(())
     }
@@ -441,3 +721,398 @@ impl fmt::Display for Downloader {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::downloader::storage::MemoryStorage;
+    use crate::downloader::strategy::create_shared_registry;
+    use crate::request::cookie_jar::CookieJar;
+    use crate::request::header_profile::HeaderProfile;
+    use crate::request::proxy::ProxyConfig;
+    use crate::request::tls::TlsConfig;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::watch;
+    use tokio::time::{sleep, Duration};
+
+    #[derive(Clone, Copy)]
+    enum Fault {
+        None,
+        // The first requests answer with 500.
+        ServerErrors(usize),
+        // Every range is answered one byte short, with a matching Content-Length.
+        ShortRanges,
+        NotFound,
+    }
+
+    // Serves the body with Range support, one request per connection.
+    async fn serve(body: Vec<u8>, fault: Fault) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/file.bin", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let body = Arc::new(body);
+
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let request_index = counter.fetch_add(1, Ordering::SeqCst);
+                let body = body.clone();
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0u8; 1024];
+                    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                        match socket.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => request.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let request = String::from_utf8_lossy(&request).to_ascii_lowercase();
+                    let range = request
+                        .lines()
+                        .find_map(|line| line.strip_prefix("range: bytes="))
+                        .and_then(|range| range.trim().split_once('-'))
+                        .map(|(start, end)| {
+                            let end = end.parse::<usize>().unwrap().min(body.len() - 1);
+                            (start.parse::<usize>().unwrap(), end)
+                        });
+
+                    let (status, content) = match (fault, range) {
+                        (Fault::NotFound, _) => ("404 Not Found", b"not found".to_vec()),
+                        (Fault::ServerErrors(count), _) if request_index < count => {
+                            ("500 Internal Server Error", b"error".to_vec())
+                        }
+                        (Fault::ShortRanges, Some((start, end))) => {
+                            ("206 Partial Content", body[start..end].to_vec())
+                        }
+                        (_, Some((start, end))) => {
+                            ("206 Partial Content", body[start..=end].to_vec())
+                        }
+                        (_, None) => ("200 OK", body.to_vec()),
+                    };
+                    let head = format!(
+                        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        status,
+                        content.len()
+                    );
+                    let _ = socket.write_all(head.as_bytes()).await;
+                    let _ = socket.write_all(&content).await;
+                });
+            }
+        });
+
+        (url, requests)
+    }
+
+    fn downloader_in(download_dir: &str) -> Downloader {
+        let proxy = ProxyConfig {
+            use_env: false,
+            ..ProxyConfig::default()
+        };
+        let client = Client::new(
+            &proxy,
+            &HeaderProfile::default(),
+            &TlsConfig::default(),
+            &Arc::new(CookieJar::default()),
+        )
+        .unwrap();
+        Downloader::new(
+            client,
+            1000,
+            4,
+            download_dir,
+            "{filename}",
+            create_shared_registry(),
+        )
+    }
+
+    fn downloader(preallocate: bool) -> Downloader {
+        downloader_in(".").with_preallocate(preallocate)
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("hermesdl-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn job(url: &str) -> DownloadInfo {
+        DownloadInfo {
+            url: url.to_string(),
+            ..DownloadInfo::default()
+        }
+    }
+
+    fn segments(url: &str, size: u64, segment_size: u64) -> Vec<Segment> {
+        let url = Arc::new(url.to_string());
+        (0..size)
+            .step_by(segment_size as usize)
+            .map(|start| Segment::new(url.clone(), start, (start + segment_size).min(size) - 1))
+            .collect()
+    }
+
+    fn body(size: usize) -> Vec<u8> {
+        (0..size).map(|i| (i % 251) as u8).collect()
+    }
+
+    async fn download(
+        downloader: &Downloader,
+        segments: &[Segment],
+        storage: Arc<MemoryStorage>,
+    ) -> Result<()> {
+        let progress_manager = Arc::new(ProgressManager::new("test".to_string()));
+        downloader
+            .download_segments(segments, None, storage, true, progress_manager)
+            .await
+    }
+
+    // Reports a position that only moves when the test says so.
+    struct GatedStorage {
+        inner: MemoryStorage,
+        position: watch::Sender<u64>,
+    }
+
+    impl Storage for GatedStorage {
+        fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize> {
+            self.inner.write_at(buf, offset)
+        }
+
+        fn allocate(&self, size: u64) -> Result<()> {
+            self.inner.allocate(size)
+        }
+
+        fn sync_data(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn position(&self) -> Option<watch::Receiver<u64>> {
+            Some(self.position.subscribe())
+        }
+    }
+
+    #[tokio::test]
+    async fn downloads_ranges_in_place() {
+        let body = body(10_000);
+        let (url, requests) = serve(body.clone(), Fault::None).await;
+        let storage = Arc::new(MemoryStorage::new());
+
+        download(
+            &downloader(false),
+            &segments(&url, 10_000, 1024),
+            storage.clone(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(storage.contents(), body);
+        assert_eq!(requests.load(Ordering::SeqCst), 10);
+    }
+
+    #[tokio::test]
+    async fn retries_failed_segments() {
+        let body = body(3000);
+        let (url, requests) = serve(body.clone(), Fault::ServerErrors(2)).await;
+        let storage = Arc::new(MemoryStorage::new());
+
+        download(
+            &downloader(false),
+            &segments(&url, 3000, 1000),
+            storage.clone(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(storage.contents(), body);
+        assert_eq!(requests.load(Ordering::SeqCst), 5);
+    }
+
+    #[tokio::test]
+    async fn preallocated_download_is_complete() {
+        let body = body(5000);
+        let (url, _) = serve(body.clone(), Fault::None).await;
+        let storage = Arc::new(MemoryStorage::new());
+
+        download(
+            &downloader(true),
+            &segments(&url, 5000, 700),
+            storage.clone(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(storage.contents(), body);
+    }
+
+    #[tokio::test]
+    async fn short_segments_fail_despite_preallocation() {
+        let (url, _) = serve(body(2000), Fault::ShortRanges).await;
+        let storage = Arc::new(MemoryStorage::new());
+
+        let result = download(
+            &downloader(true),
+            &segments(&url, 2000, 1000),
+            storage.clone(),
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(storage.contents().len(), 2000);
+    }
+
+    #[tokio::test]
+    async fn error_pages_are_not_written() {
+        let (url, _) = serve(body(1000), Fault::NotFound).await;
+        let storage = Arc::new(MemoryStorage::new());
+
+        let result = download(
+            &downloader(false),
+            &segments(&url, 1000, 1000),
+            storage.clone(),
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert!(storage.contents().is_empty());
+    }
+
+    #[tokio::test]
+    async fn segments_wait_for_the_storage_position() {
+        let body = body(10_000);
+        let (url, requests) = serve(body.clone(), Fault::None).await;
+        let storage = Arc::new(GatedStorage {
+            inner: MemoryStorage::new(),
+            position: watch::Sender::new(0),
+        });
+        let downloader = downloader(false).with_writer_options(WriterOptions {
+            buffer_size: 100,
+            memory_budget: 1000,
+            ..WriterOptions::default()
+        });
+
+        let segments = segments(&url, 10_000, 1000);
+        let progress_manager = Arc::new(ProgressManager::new("test".to_string()));
+        let task = tokio::spawn({
+            let storage = storage.clone();
+            async move {
+                downloader
+                    .download_segments(&segments, None, storage, true, progress_manager)
+                    .await
+            }
+        });
+
+        sleep(Duration::from_millis(500)).await;
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        storage.position.send_replace(10_000);
+        task.await.unwrap().unwrap();
+        assert_eq!(storage.inner.contents(), body);
+    }
+
+    #[tokio::test]
+    async fn pages_without_media_are_saved() {
+        let page = b"<html><body><p>No media here</p></body></html>".to_vec();
+        let (url, _) = serve(page.clone(), Fault::None).await;
+        let dir = temp_dir("page");
+
+        downloader_in(dir.to_str().unwrap())
+            .download(&job(&url))
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read(dir.join("file.bin")).unwrap(), page);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn nested_metalinks_stop() {
+        let metalink = br#"<?xml version="1.0"?>
+            <metalink xmlns="urn:ietf:params:xml:ns:metalink">
+              <file name="loop.bin"><url>file.bin</url></file>
+            </metalink>"#
+            .to_vec();
+        let (url, requests) = serve(metalink, Fault::None).await;
+        let dir = temp_dir("metalink");
+
+        let result = downloader_in(dir.to_str().unwrap())
+            .download(&job(&url))
+            .await;
+
+        assert!(result.is_err());
+        assert!(requests.load(Ordering::SeqCst) <= 3 * (constant::MAX_NESTING_DEPTH + 1));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn job_output_names_create_directories() {
+        let body = body(2500);
+        let (url, _) = serve(body.clone(), Fault::None).await;
+        let dir = temp_dir("out");
+        let job = DownloadInfo {
+            out: Some("{host}/../sub/{title}.copy.{ext}".to_string()),
+            ..job(&url)
+        };
+
+        downloader_in(dir.to_str().unwrap())
+            .download(&job)
+            .await
+            .unwrap();
+
+        let path = dir.join("127.0.0.1").join("sub").join("file.copy.bin");
+        assert_eq!(std::fs::read(path).unwrap(), body);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sizes_segments_from_byte_ranges() {
+        let url = "http://127.0.0.1:9/media.mp4".to_string();
+        let segments = downloader(false)
+            .get_segments_info(vec![
+                (url.clone(), Some((0, 99))),
+                (url.clone(), Some((100, 100))),
+                (url.clone(), Some((500, 999))),
+            ])
+            .await
+            .unwrap();
+
+        let bounds: Vec<_> = segments
+            .iter()
+            .map(|s| (s.start, s.end, s.source_range))
+            .collect();
+        assert_eq!(
+            bounds,
+            [
+                (0, 99, Some((0, 99))),
+                (100, 100, Some((100, 100))),
+                (101, 600, Some((500, 999))),
+            ]
+        );
+
+        let result = downloader(false)
+            .get_segments_info(vec![(url.clone(), Some((0, 99))), (url, Some((200, 100)))])
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn job_output_names_replace_the_template() {
+        let body = body(1500);
+        let (url, _) = serve(body.clone(), Fault::None).await;
+        let dir = temp_dir("template");
+        let mut downloader = downloader_in(dir.to_str().unwrap());
+        downloader.output = downloader.output.with_template("{host}/{title}-copy.{ext}");
+
+        let named = DownloadInfo {
+            out: Some("a/b.mp4".to_string()),
+            ..job(&url)
+        };
+        downloader.download(&named).await.unwrap();
+        downloader.download(&job(&url)).await.unwrap();
+
+        assert_eq!(std::fs::read(dir.join("a").join("b.mp4")).unwrap(), body);
+        let templated = dir.join("127.0.0.1").join("file-copy.bin");
+        assert_eq!(std::fs::read(templated).unwrap(), body);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
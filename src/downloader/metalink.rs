@@ -0,0 +1,62 @@
+use anyhow::{anyhow, Result};
+use roxmltree::{Document, Node};
+use url::Url;
+
+#[derive(Clone, Debug)]
+pub struct MetalinkFile {
+    pub name: String,
+    pub urls: Vec<String>,
+}
+
+pub fn parse_metalink(metalink: &str, metalink_url: &Url) -> Result<Vec<MetalinkFile>> {
+    let document = Document::parse(metalink)?;
+    let root = document.root_element();
+    if !root.has_tag_name("metalink") {
+        return Err(anyhow!("Not a Metalink document: {}", metalink_url));
+    }
+
+    // Metalink 4 lists files directly under the root, Metalink 3 nests them in <files>.
+    let files = root
+        .children()
+        .filter(|n| n.has_tag_name("files"))
+        .flat_map(|n| n.children())
+        .chain(root.children())
+        .filter(|n| n.has_tag_name("file"));
+
+    let mut result = Vec::new();
+    for file in files {
+        let name = file.attribute("name").unwrap_or_default().to_string();
+        let mut urls: Vec<(i64, String)> = file
+            .descendants()
+            .filter(|n| n.has_tag_name("url"))
+            .filter_map(|n| {
+                let url = metalink_url.join(n.text()?.trim()).ok()?;
+                matches!(url.scheme(), "http" | "https").then(|| (priority(n), url.to_string()))
+            })
+            .collect();
+        urls.sort_by_key(|(priority, _)| *priority);
+
+        if !urls.is_empty() {
+            result.push(MetalinkFile {
+                name,
+                urls: urls.into_iter().map(|(_, url)| url).collect(),
+            });
+        }
+    }
+
+    Ok(result)
+}
+
+fn priority(node: Node) -> i64 {
+    // Metalink 4 "priority" ascends, Metalink 3 "preference" descends.
+    if let Some(priority) = node.attribute("priority").and_then(|p| p.parse().ok()) {
+        priority
+    } else if let Some(preference) = node
+        .attribute("preference")
+        .and_then(|p| p.parse::<i64>().ok())
+    {
+        -preference
+    } else {
+        i64::MAX
+    }
+}
@@ -0,0 +1,295 @@
+use super::constant;
+use super::template::TemplateContext;
+use super::writer::FsyncPolicy;
+use anyhow::{anyhow, Result};
+use std::fs::{self, OpenOptions};
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Clone, Debug)]
+pub struct OutputResolver {
+    root: PathBuf,
+    template: String,
+}
+
+impl OutputResolver {
+    pub fn new(root: impl Into<PathBuf>, template: &str) -> Self {
+        Self {
+            root: root.into(),
+            template: template.to_string(),
+        }
+    }
+
+    pub fn with_template(&self, template: &str) -> Self {
+        Self::new(self.root.clone(), template)
+    }
+
+    pub fn check_free_space(&self, required: u64) -> Result<()> {
+        fs::create_dir_all(&self.root)?;
+        let available = fs2::available_space(&self.root)?;
+        let required = required + constant::DISK_SPACE_MARGIN;
+        if available < required {
+            return Err(anyhow!(
+                "Not enough disk space in {}: {} bytes required, {} bytes available",
+                self.root.display(),
+                required,
+                available
+            ));
+        }
+        Ok(())
+    }
+
+    // Creates the empty ".part" file for the download inside the root directory.
+    // It is created exclusively, so concurrent jobs never share the same name.
+    pub fn reserve(&self, url: &str, dir: Option<&str>, filename: &str) -> Result<ReservedOutput> {
+        let context = TemplateContext::new(url, filename);
+        let mut relative_path = PathBuf::new();
+        if let Some(dir) = dir {
+            relative_path.push(sanitize_dir(&context.render(dir)));
+        }
+        relative_path.push(sanitize_dir(&context.render(&self.template)));
+
+        let filename = relative_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| constant::DEFAULT_FILENAME.to_string());
+        let directory = self
+            .root
+            .join(relative_path.parent().unwrap_or(Path::new("")));
+        let numbered = filename.contains(constant::COUNTER_PLACEHOLDER);
+        let (stem, extension) = split_extension(&filename);
+
+        for count in 0..constant::MAX_NAME_COLLISIONS {
+            let candidate = match (numbered, count, extension) {
+                (true, _, _) => {
+                    filename.replace(constant::COUNTER_PLACEHOLDER, &(count + 1).to_string())
+                }
+                (false, 0, _) => filename.clone(),
+                (false, _, Some(extension)) => format!("{} ({}).{}", stem, count, extension),
+                (false, _, None) => format!("{} ({})", stem, count),
+            };
+            let path = self.confine(&directory)?.join(candidate);
+            if path.exists() {
+                continue;
+            }
+            let part_path = part_path(&path);
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&part_path)
+            {
+                Ok(_) => return Ok(ReservedOutput { path, part_path }),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Err(anyhow!("No free file name for {}", filename))
+    }
+
+    // The path below the root with "/" separators, e.g. to name an uploaded object.
+    pub fn relative_key(&self, path: &Path) -> Result<String> {
+        let root = self.root.canonicalize()?;
+        let relative = path.strip_prefix(&root)?;
+        let parts: Vec<String> = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().to_string())
+            .collect();
+        Ok(parts.join("/"))
+    }
+
+    // Symlinks inside the download folder must not lead out of it. The part that exists is
+    // checked before anything is created, the created part holds no symlinks then.
+    fn confine(&self, directory: &Path) -> Result<PathBuf> {
+        fs::create_dir_all(&self.root)?;
+        let root = self.root.canonicalize()?;
+
+        let existing = directory
+            .ancestors()
+            .find(|ancestor| fs::symlink_metadata(ancestor).is_ok())
+            .ok_or_else(|| anyhow!("No existing parent of {}", directory.display()))?;
+        check_inside(&existing.canonicalize()?, &root)?;
+
+        fs::create_dir_all(directory)?;
+        let directory = directory.canonicalize()?;
+        check_inside(&directory, &root)?;
+        Ok(directory)
+    }
+}
+
+#[derive(Debug)]
+pub struct ReservedOutput {
+    pub path: PathBuf,
+    pub part_path: PathBuf,
+}
+
+impl ReservedOutput {
+    // Flushes the finished ".part" file and moves it to its final name. Its size is checked
+    // while writing, a preallocated file has the full length from the start.
+    // This is the single durability point unless the policy disables fsync.
+    pub fn commit(&self, fsync_policy: FsyncPolicy) -> Result<()> {
+        let file = OpenOptions::new().write(true).open(&self.part_path)?;
+        let sync = fsync_policy != FsyncPolicy::Never;
+        if sync {
+            file.sync_all()?;
+        }
+        drop(file);
+
+        // A hard link never replaces a file that appeared under the final name meanwhile.
+        match fs::hard_link(&self.part_path, &self.path) {
+            Ok(_) => fs::remove_file(&self.part_path)?,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                return Err(anyhow!("{} already exists", self.path.display()))
+            }
+            Err(_) => fs::rename(&self.part_path, &self.path)?,
+        }
+
+        if let Some(parent) = self.path.parent().filter(|_| sync) {
+            sync_dir(parent);
+        }
+        Ok(())
+    }
+
+    pub fn discard(&self) {
+        if let Err(e) = fs::remove_file(&self.part_path) {
+            eprintln!("Failed to remove {}: {}", self.part_path.display(), e);
+        }
+    }
+}
+
+fn check_inside(directory: &Path, root: &Path) -> Result<()> {
+    if !directory.starts_with(root) {
+        return Err(anyhow!(
+            "Output directory {} is outside of {}",
+            directory.display(),
+            root.display()
+        ));
+    }
+    Ok(())
+}
+
+pub fn sanitize_filename(filename: &str) -> String {
+    let sanitized: String = filename
+        .chars()
+        .map(|c| {
+            if c.is_control() || constant::RESERVED_FILENAME_CHARS.contains(&c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let sanitized = sanitized.trim_matches(|c: char| c == '.' || c.is_whitespace());
+
+    if sanitized.is_empty() {
+        return constant::DEFAULT_FILENAME.to_string();
+    }
+
+    let (stem, extension) = split_extension(sanitized);
+    let stem = if constant::RESERVED_FILENAMES.contains(&stem.to_ascii_uppercase().as_str()) {
+        format!("_{}", stem)
+    } else {
+        stem.to_string()
+    };
+
+    // Keep the extension and shorten the stem until the name fits the file system limit.
+    let extension = extension.map(|e| format!(".{}", e)).unwrap_or_default();
+    let mut stem = stem;
+    let max_length = constant::MAX_FILENAME_LENGTH - constant::PART_EXTENSION.len() - 1;
+    while stem.len() + extension.len() > max_length && stem.pop().is_some() {}
+    if stem.is_empty() {
+        stem = constant::DEFAULT_FILENAME.to_string();
+    }
+
+    format!("{}{}", stem, extension)
+}
+
+fn sanitize_dir(dir: &str) -> PathBuf {
+    Path::new(dir)
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(sanitize_filename(&part.to_string_lossy())),
+            _ => None,
+        })
+        .collect()
+}
+
+fn split_extension(filename: &str) -> (&str, Option<&str>) {
+    match filename.rsplit_once('.') {
+        Some((stem, extension))
+            if !stem.is_empty()
+                && !extension.is_empty()
+                && extension.len() <= constant::MAX_EXTENSION_LENGTH =>
+        {
+            (stem, Some(extension))
+        }
+        _ => (filename, None),
+    }
+}
+
+fn part_path(path: &Path) -> PathBuf {
+    let mut filename = path.file_name().unwrap_or_default().to_os_string();
+    filename.push(".");
+    filename.push(constant::PART_EXTENSION);
+    path.with_file_name(filename)
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) {
+    // Persists the rename itself, failures only weaken durability.
+    if let Ok(dir) = fs::File::open(dir) {
+        let _ = dir.sync_all();
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("hermesdl-output-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn reserves_unique_names() {
+        let root = temp_dir("unique");
+        let output = OutputResolver::new(&root, "{host}/{filename}");
+
+        let first = output
+            .reserve("https://a.test/x", Some("videos"), "clip.mp4")
+            .unwrap();
+        fs::write(&first.path, b"done").unwrap();
+        let second = output
+            .reserve("https://a.test/x", Some("videos"), "clip.mp4")
+            .unwrap();
+
+        let directory = root.canonicalize().unwrap().join("videos").join("a.test");
+        assert_eq!(first.path, directory.join("clip.mp4"));
+        assert_eq!(second.path, directory.join("clip (1).mp4"));
+        assert!(second.part_path.exists());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn creates_nothing_behind_symlinks_leaving_the_root() {
+        let root = temp_dir("root");
+        let outside = temp_dir("outside");
+        std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+        let output = OutputResolver::new(&root, "{filename}");
+
+        let result = output.reserve("https://a.test/x", Some("link/new/deeper"), "clip.mp4");
+
+        assert!(result.is_err());
+        assert!(!outside.join("new").exists());
+        fs::remove_dir_all(root).unwrap();
+        fs::remove_dir_all(outside).unwrap();
+    }
+}
@@ -0,0 +1,33 @@
+use super::category::{matches_hosts, matches_url_pattern};
+use crate::request::proxy::ProxyConfig;
+use crate::request::tls::TlsConfig;
+use crate::request::user_agent::UserAgent;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Profile {
+    pub name: String,
+    pub hosts: Vec<String>,
+    pub url_pattern: Option<String>,
+    pub use_tor: Option<bool>,
+    pub proxy: Option<ProxyConfig>,
+    pub tls: Option<TlsConfig>,
+    pub user_agent: Option<UserAgent>,
+    pub chunk_size: Option<u64>,
+    pub max_concurrent_count: Option<usize>,
+    pub headers: HashMap<String, String>,
+}
+
+impl Profile {
+    // Matched against the job URL before anything is requested, unlike category rules.
+    pub fn matches(&self, url: &str) -> bool {
+        matches_hosts(&self.hosts, url)
+            && matches_url_pattern(
+                self.url_pattern.as_deref(),
+                url,
+                &format!("profile {}", self.name),
+            )
+    }
+}
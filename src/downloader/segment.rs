@@ -6,15 +6,27 @@ pub struct Segment {
     pub url: Arc<String>,
     pub start: u64,
     pub end: u64,
+    pub source_range: Option<(u64, u64)>,
 }
 
 impl Segment {
     pub fn new(url: Arc<String>, start: u64, end: u64) -> Self {
-        Self { url, start, end }
+        Self {
+            url,
+            start,
+            end,
+            source_range: None,
+        }
+    }
+
+    pub fn with_source_range(mut self, source_range: Option<(u64, u64)>) -> Self {
+        self.source_range = source_range;
+        self
     }
 
     pub fn get_range_header(&self) -> String {
-        format!("bytes={}-{}", self.start, self.end)
+        let (start, end) = self.source_range.unwrap_or((self.start, self.end));
+        format!("bytes={}-{}", start, end)
     }
 }
 
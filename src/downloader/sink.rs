@@ -0,0 +1,272 @@
+use super::storage::Storage;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use tokio::sync::watch;
+use tokio::task;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkTarget {
+    Stdout,
+    Pipe {
+        path: String,
+    },
+    Process {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+}
+
+impl SinkTarget {
+    pub async fn open(&self) -> Result<Sink> {
+        let (output, child): (Box<dyn Write + Send>, _) = match self {
+            SinkTarget::Stdout => (Box::new(io::stdout()), None),
+            // Opening a named pipe waits until the reading side is connected.
+            SinkTarget::Pipe { path } => {
+                let path = path.clone();
+                let pipe = task::spawn_blocking(move || OpenOptions::new().write(true).open(path))
+                    .await??;
+                (Box::new(pipe), None)
+            }
+            SinkTarget::Process { command, args } => {
+                let mut child = Command::new(command)
+                    .args(args)
+                    .stdin(Stdio::piped())
+                    .spawn()?;
+                let stdin = child
+                    .stdin
+                    .take()
+                    .ok_or_else(|| anyhow!("Failed to open stdin of {}", command))?;
+                (Box::new(stdin), Some(child))
+            }
+        };
+
+        Ok(Sink {
+            storage: Arc::new(OrderedStorage::new(output)?),
+            child,
+        })
+    }
+}
+
+pub struct Sink {
+    storage: Arc<OrderedStorage>,
+    child: Option<Child>,
+}
+
+impl Sink {
+    pub fn storage(&self) -> Arc<dyn Storage> {
+        self.storage.clone()
+    }
+
+    pub async fn finish(mut self) -> Result<()> {
+        let storage = self.storage.clone();
+        let result = task::spawn_blocking(move || storage.close()).await?;
+        if let Some(mut child) = self.child.take() {
+            let status = task::spawn_blocking(move || child.wait()).await??;
+            if !status.success() {
+                return result.and(Err(anyhow!("Output process exited with {}", status)));
+            }
+        }
+        result
+    }
+}
+
+enum SinkMessage {
+    Data(Vec<u8>),
+    Flush,
+}
+
+// Segments arrive out of order, so data is held back until everything before it was written.
+// A pipe or process may read slowly, so a thread of its own writes to it. The position is what
+// that thread has written, writers wait for it before they start, which bounds both the data
+// held back and the data queued for the thread.
+struct OrderedStorage {
+    state: Mutex<OrderedState>,
+    position: watch::Receiver<u64>,
+}
+
+struct OrderedState {
+    queue: Option<mpsc::Sender<SinkMessage>>,
+    output_thread: Option<JoinHandle<Result<()>>>,
+    position: u64,
+    pending: BTreeMap<u64, Vec<u8>>,
+}
+
+impl OrderedStorage {
+    fn new(output: Box<dyn Write + Send>) -> Result<Self> {
+        let (queue, messages) = mpsc::channel();
+        let (position, position_receiver) = watch::channel(0);
+        let output_thread = thread::Builder::new()
+            .name("sink-output".to_string())
+            .spawn(move || write_output(output, messages, position))?;
+
+        Ok(Self {
+            state: Mutex::new(OrderedState {
+                queue: Some(queue),
+                output_thread: Some(output_thread),
+                position: 0,
+                pending: BTreeMap::new(),
+            }),
+            position: position_receiver,
+        })
+    }
+
+    // Blocks until the output thread is done, the output is dropped in any case, so a reading
+    // process sees the end of its input.
+    fn close(&self) -> Result<()> {
+        let (output_thread, missing) = {
+            let mut state = self.state.lock().unwrap();
+            state.queue = None;
+            let missing = state
+                .pending
+                .keys()
+                .next()
+                .map(|offset| (state.position, *offset));
+            (state.output_thread.take(), missing)
+        };
+        if let Some(output_thread) = output_thread {
+            output_thread
+                .join()
+                .map_err(|_| anyhow!("Output thread panicked"))??;
+        }
+        match missing {
+            Some((position, offset)) => Err(anyhow!(
+                "Missing data at offset {}, next data starts at {}",
+                position,
+                offset
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+fn write_output(
+    mut output: Box<dyn Write + Send>,
+    messages: mpsc::Receiver<SinkMessage>,
+    position: watch::Sender<u64>,
+) -> Result<()> {
+    // Returning drops the position sender, so writers waiting for it stop as well.
+    for message in messages {
+        match message {
+            SinkMessage::Data(data) => {
+                output.write_all(&data)?;
+                position.send_modify(|position| *position += data.len() as u64);
+            }
+            SinkMessage::Flush => output.flush()?,
+        }
+    }
+    output.flush()?;
+    Ok(())
+}
+
+impl OrderedState {
+    fn emit(&mut self, buf: &[u8], offset: u64) -> Result<()> {
+        // A retried segment repeats data that was already written.
+        let end = offset + buf.len() as u64;
+        if end <= self.position {
+            return Ok(());
+        }
+        let skip = self.position.saturating_sub(offset) as usize;
+        self.send(SinkMessage::Data(buf[skip..].to_vec()))?;
+        self.position = end;
+        Ok(())
+    }
+
+    fn send(&self, message: SinkMessage) -> Result<()> {
+        self.queue
+            .as_ref()
+            .ok_or_else(|| anyhow!("Output is already closed"))?
+            .send(message)
+            .map_err(|_| anyhow!("Output failed, see the error when it closes"))
+    }
+}
+
+impl Storage for OrderedStorage {
+    fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        if offset > state.position {
+            let pending = state.pending.entry(offset).or_default();
+            if pending.len() < buf.len() {
+                *pending = buf.to_vec();
+            }
+            return Ok(buf.len());
+        }
+
+        state.emit(buf, offset)?;
+        while let Some((offset, data)) = state.pending.pop_first() {
+            if offset > state.position {
+                state.pending.insert(offset, data);
+                break;
+            }
+            state.emit(&data, offset)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn allocate(&self, _size: u64) -> Result<()> {
+        Ok(())
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        self.state.lock().unwrap().send(SinkMessage::Flush)
+    }
+
+    fn position(&self) -> Option<watch::Receiver<u64>> {
+        Some(self.position.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    // A reader that takes its time with every write.
+    struct SlowOutput {
+        data: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl Write for SlowOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            thread::sleep(Duration::from_millis(100));
+            self.data.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn writes_in_order_without_waiting_for_the_output() {
+        let data = Arc::new(Mutex::new(Vec::new()));
+        let storage = OrderedStorage::new(Box::new(SlowOutput { data: data.clone() })).unwrap();
+
+        let started = Instant::now();
+        storage.write_all_at(b"world", 6).unwrap();
+        storage.write_all_at(b"hello ", 0).unwrap();
+        storage.write_all_at(b"!", 11).unwrap();
+        assert!(started.elapsed() < Duration::from_millis(100));
+
+        storage.close().unwrap();
+        assert_eq!(&data.lock().unwrap()[..], b"hello world!");
+        assert_eq!(*storage.position().unwrap().borrow(), 12);
+    }
+
+    #[test]
+    fn reports_missing_data() {
+        let data = Arc::new(Mutex::new(Vec::new()));
+        let storage = OrderedStorage::new(Box::new(SlowOutput { data })).unwrap();
+
+        storage.write_all_at(b"late", 10).unwrap();
+        assert!(storage.close().is_err());
+    }
+}
@@ -0,0 +1,118 @@
+use anyhow::{anyhow, Result};
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+use tokio::sync::watch;
+
+pub trait Storage: Send + Sync {
+    fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize>;
+
+    fn allocate(&self, size: u64) -> Result<()>;
+
+    fn sync_data(&self) -> Result<()>;
+
+    // Storages that write in order report how far they got, writers then stay close behind.
+    fn position(&self) -> Option<watch::Receiver<u64>> {
+        None
+    }
+
+    fn write_all_at(&self, mut buf: &[u8], mut offset: u64) -> Result<()> {
+        while !buf.is_empty() {
+            match self.write_at(buf, offset)? {
+                0 => return Err(anyhow!("Failed to write at offset {}", offset)),
+                n => {
+                    buf = &buf[n..];
+                    offset += n as u64;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct FileStorage {
+    file: File,
+}
+
+impl FileStorage {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+        Ok(Self { file })
+    }
+
+    pub fn create(path: &Path) -> Result<Self> {
+        Ok(Self {
+            file: File::create(path)?,
+        })
+    }
+}
+
+impl Storage for FileStorage {
+    #[cfg(target_family = "unix")]
+    fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize> {
+        use std::os::unix::fs::FileExt;
+        Ok(self.file.write_at(buf, offset)?)
+    }
+
+    // The cursor moves as well, which is harmless since every write passes its offset.
+    #[cfg(target_family = "windows")]
+    fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize> {
+        use std::os::windows::fs::FileExt;
+        Ok(self.file.seek_write(buf, offset)?)
+    }
+
+    fn allocate(&self, size: u64) -> Result<()> {
+        Ok(fs2::FileExt::allocate(&self.file, size)?)
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        Ok(self.file.sync_data()?)
+    }
+}
+
+// Keeps the whole download in memory, so the downloader can be tested without a file system.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryStorage {
+    data: std::sync::Mutex<Vec<u8>>,
+}
+
+#[cfg(test)]
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contents(&self) -> Vec<u8> {
+        self.data.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+impl Storage for MemoryStorage {
+    fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize> {
+        let mut data = self.data.lock().unwrap();
+        let start = offset as usize;
+        let end = start + buf.len();
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[start..end].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn allocate(&self, size: u64) -> Result<()> {
+        let mut data = self.data.lock().unwrap();
+        if data.len() < size as usize {
+            data.resize(size as usize, 0);
+        }
+        Ok(())
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,199 @@
+mod dash;
+mod hls;
+mod metalink;
+mod page;
+mod ranged;
+mod single;
+
+use super::constant;
+use super::detector::StreamKind;
+use super::extractor::ExtractorRegistry;
+use super::manager::Downloader;
+use super::progress::ProgressManager;
+use super::segment::Segment;
+use super::storage::Storage;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+pub use dash::DashStrategy;
+pub use hls::HlsStrategy;
+pub use metalink::MetalinkStrategy;
+pub use page::PageStrategy;
+pub use ranged::RangedHttpStrategy;
+pub use single::SingleStreamStrategy;
+
+#[derive(Clone, Debug)]
+pub struct Probe {
+    pub url: String,
+    pub headers: Option<HashMap<String, String>>,
+    pub kind: StreamKind,
+    pub filename: String,
+    pub content_length: Option<u64>,
+    pub accept_ranges: bool,
+    pub content_type: Option<String>,
+    pub dir: Option<String>,
+    // Directories of the output name given by the job, created below the directory.
+    pub out_dir: Option<String>,
+    // The job named its output, so the filename template does not apply.
+    pub explicit_name: bool,
+    pub sink: Option<String>,
+}
+
+impl Probe {
+    pub fn output_dir(&self) -> Option<String> {
+        match (&self.dir, &self.out_dir) {
+            (Some(dir), Some(out_dir)) => Some(format!("{}/{}", dir, out_dir)),
+            (dir, out_dir) => dir.clone().or_else(|| out_dir.clone()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Plan {
+    pub filename: String,
+    pub segments: Vec<Segment>,
+    pub ranged: bool,
+}
+
+impl Plan {
+    pub fn size(&self) -> u64 {
+        self.segments.iter().map(|s| s.end - s.start + 1).sum()
+    }
+
+    pub fn expected_size(&self) -> Option<u64> {
+        Some(self.size()).filter(|size| *size > 0)
+    }
+}
+
+pub trait DownloadStrategy: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn probe(&self, probe: &Probe) -> bool;
+
+    fn plan<'a>(
+        &'a self,
+        downloader: &'a Downloader,
+        probe: &'a Probe,
+    ) -> BoxFuture<'a, Result<Vec<Plan>>>;
+
+    fn required_space(&self, plans: &[Plan]) -> u64 {
+        plans.iter().map(|plan| plan.size()).sum()
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        downloader: &'a Downloader,
+        probe: &'a Probe,
+        plans: &'a [Plan],
+        progress_manager: Arc<ProgressManager>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            for plan in plans {
+                let output = downloader.output().reserve(
+                    &probe.url,
+                    probe.output_dir().as_deref(),
+                    &plan.filename,
+                )?;
+                let result = downloader
+                    .download_parallel(
+                        &plan.segments,
+                        probe.headers.as_ref(),
+                        &output.part_path,
+                        plan.ranged,
+                        progress_manager.clone(),
+                    )
+                    .await
+                    .and_then(|_| output.commit(downloader.fsync_policy()));
+                if let Err(e) = result {
+                    output.discard();
+                    return Err(e);
+                }
+                downloader.upload(&output.path).await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn stream<'a>(
+        &'a self,
+        downloader: &'a Downloader,
+        probe: &'a Probe,
+        plan: &'a Plan,
+        storage: Arc<dyn Storage>,
+        progress_manager: Arc<ProgressManager>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(downloader.download_segments(
+            &plan.segments,
+            probe.headers.as_ref(),
+            storage,
+            plan.ranged,
+            progress_manager,
+        ))
+    }
+
+    fn finalize<'a>(
+        &'a self,
+        _downloader: &'a Downloader,
+        _probe: &'a Probe,
+        _plans: &'a [Plan],
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+// Segment sizes of streams come from HEAD requests and are only estimates.
+fn estimated_space(plans: &[Plan]) -> u64 {
+    let size: u64 = plans.iter().map(|plan| plan.size()).sum();
+    size + size / constant::STREAM_SPACE_OVERHEAD_DIVISOR
+}
+
+pub type SharedRegistry = Arc<StrategyRegistry>;
+
+pub struct StrategyRegistry {
+    strategies: Vec<Arc<dyn DownloadStrategy>>,
+}
+
+impl StrategyRegistry {
+    pub fn new() -> Self {
+        Self {
+            strategies: Vec::new(),
+        }
+    }
+
+    pub fn register(&mut self, strategy: Arc<dyn DownloadStrategy>) {
+        // Later registrations take precedence, so custom strategies shadow the built-in ones.
+        self.strategies.insert(0, strategy);
+    }
+
+    pub fn find(&self, probe: &Probe) -> Option<Arc<dyn DownloadStrategy>> {
+        self.strategies.iter().find(|s| s.probe(probe)).cloned()
+    }
+}
+
+impl Default for StrategyRegistry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register(Arc::new(SingleStreamStrategy));
+        registry.register(Arc::new(RangedHttpStrategy));
+        registry.register(Arc::new(PageStrategy::new(ExtractorRegistry::default())));
+        registry.register(Arc::new(MetalinkStrategy));
+        registry.register(Arc::new(DashStrategy));
+        registry.register(Arc::new(HlsStrategy));
+        registry
+    }
+}
+
+impl fmt::Debug for StrategyRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.strategies.iter().map(|s| s.name()))
+            .finish()
+    }
+}
+
+pub fn create_shared_registry() -> SharedRegistry {
+    Arc::new(StrategyRegistry::default())
+}
@@ -0,0 +1,67 @@
+use super::{estimated_space, DownloadStrategy, Plan, Probe};
+use crate::downloader::dash;
+use crate::downloader::detector::StreamKind;
+use crate::downloader::manager::Downloader;
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use std::path::Path;
+use url::Url;
+
+pub struct DashStrategy;
+
+impl DownloadStrategy for DashStrategy {
+    fn name(&self) -> &'static str {
+        "dash"
+    }
+
+    fn probe(&self, probe: &Probe) -> bool {
+        probe.kind == StreamKind::Dash
+    }
+
+    fn required_space(&self, plans: &[Plan]) -> u64 {
+        estimated_space(plans)
+    }
+
+    fn plan<'a>(
+        &'a self,
+        downloader: &'a Downloader,
+        probe: &'a Probe,
+    ) -> BoxFuture<'a, Result<Vec<Plan>>> {
+        Box::pin(async move {
+            // dash manifest
+            let get_response = downloader
+                .client()
+                .get(&probe.url, probe.headers.as_ref())
+                .await?;
+            let manifest = get_response.text().await.unwrap_or_default();
+            let tracks =
+                dash::select_tracks(dash::parse_manifest(&manifest, &Url::parse(&probe.url)?)?);
+            if tracks.is_empty() {
+                return Err(anyhow!("No downloadable tracks in {}", probe.url));
+            }
+
+            let stem = Path::new(&probe.filename)
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+
+            let mut plans = Vec::new();
+            for track in tracks {
+                let filename = format!("{}.{}.{}", stem, track.kind, track.extension());
+                let sources = track
+                    .segments
+                    .into_iter()
+                    .map(|segment| (segment.url, segment.range))
+                    .collect();
+                plans.push(Plan {
+                    filename,
+                    segments: downloader.get_segments_info(sources).await?,
+                    ranged: false,
+                });
+            }
+
+            Ok(plans)
+        })
+    }
+}
@@ -0,0 +1,74 @@
+use super::{estimated_space, DownloadStrategy, Plan, Probe};
+use crate::downloader::constant;
+use crate::downloader::detector::StreamKind;
+use crate::downloader::manager::Downloader;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use std::path::Path;
+use url::Url;
+
+pub struct HlsStrategy;
+
+impl DownloadStrategy for HlsStrategy {
+    fn name(&self) -> &'static str {
+        "hls"
+    }
+
+    fn probe(&self, probe: &Probe) -> bool {
+        probe.kind == StreamKind::Hls
+    }
+
+    fn required_space(&self, plans: &[Plan]) -> u64 {
+        estimated_space(plans)
+    }
+
+    fn plan<'a>(
+        &'a self,
+        downloader: &'a Downloader,
+        probe: &'a Probe,
+    ) -> BoxFuture<'a, Result<Vec<Plan>>> {
+        Box::pin(async move {
+            // ts playlist
+            let path = Path::new(&probe.filename);
+            let filename = match path.extension().and_then(|e| e.to_str()) {
+                Some(extension) if constant::HLS_EXTENSIONS.contains(&extension) => {
+                    path.with_extension("ts").to_string_lossy().to_string()
+                }
+                _ => probe.filename.clone(),
+            };
+
+            let get_response = downloader
+                .client()
+                .get(&probe.url, probe.headers.as_ref())
+                .await?;
+            let base_url = Url::parse(&probe.url)?;
+            let playlist: Vec<(String, Option<(u64, u64)>)> = get_response
+                .text()
+                .await
+                .unwrap_or_default()
+                .lines()
+                .filter_map(|line| {
+                    if (!line.starts_with("#")) && line.contains(".") {
+                        let ts_url = if let Ok(absolute_url) = Url::parse(line) {
+                            Some(absolute_url.to_string())
+                        } else {
+                            base_url.join(line).ok().map(|u| u.to_string())
+                        };
+                        if let Some(url) = ts_url {
+                            return Some((url, None));
+                        }
+                    }
+                    None
+                })
+                .collect();
+
+            let segments = downloader.get_segments_info(playlist).await?;
+
+            Ok(vec![Plan {
+                filename,
+                segments,
+                ranged: false,
+            }])
+        })
+    }
+}
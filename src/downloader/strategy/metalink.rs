@@ -0,0 +1,78 @@
+use super::{DownloadStrategy, Plan, Probe};
+use crate::downloader::detector::StreamKind;
+use crate::downloader::dto::DownloadInfo;
+use crate::downloader::manager::Downloader;
+use crate::downloader::metalink;
+use crate::downloader::progress::ProgressManager;
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use std::sync::Arc;
+use url::Url;
+
+pub struct MetalinkStrategy;
+
+impl DownloadStrategy for MetalinkStrategy {
+    fn name(&self) -> &'static str {
+        "metalink"
+    }
+
+    fn probe(&self, probe: &Probe) -> bool {
+        probe.kind == StreamKind::Metalink
+    }
+
+    fn plan<'a>(
+        &'a self,
+        _downloader: &'a Downloader,
+        _probe: &'a Probe,
+    ) -> BoxFuture<'a, Result<Vec<Plan>>> {
+        // Every listed file is planned by the strategy of its own mirror.
+        Box::pin(async { Ok(Vec::new()) })
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        downloader: &'a Downloader,
+        probe: &'a Probe,
+        _plans: &'a [Plan],
+        _progress_manager: Arc<ProgressManager>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let get_response = downloader
+                .client()
+                .get(&probe.url, probe.headers.as_ref())
+                .await?;
+            let document = get_response.text().await.unwrap_or_default();
+            let files = metalink::parse_metalink(&document, &Url::parse(&probe.url)?)?;
+            if files.is_empty() {
+                return Err(anyhow!("No downloadable files in {}", probe.url));
+            }
+
+            let downloader = downloader.nested()?;
+            for file in files {
+                let mut downloaded = false;
+                // Mirrors are tried in priority order until one succeeds.
+                for mirror in &file.urls {
+                    let info = DownloadInfo {
+                        url: mirror.clone(),
+                        filename: Some(file.name.clone()).filter(|name| !name.is_empty()),
+                        dir: probe.output_dir(),
+                        sink: probe.sink.clone(),
+                        ..DownloadInfo::default()
+                    };
+                    match downloader.download(&info).await {
+                        Ok(_) => {
+                            downloaded = true;
+                            break;
+                        }
+                        Err(e) => eprintln!("Failed to download mirror {}: {}", mirror, e),
+                    }
+                }
+                if !downloaded {
+                    return Err(anyhow!("Failed to download {} from any mirror", file.name));
+                }
+            }
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,103 @@
+use super::{DownloadStrategy, Plan, Probe};
+use crate::downloader::detector::StreamKind;
+use crate::downloader::dto::DownloadInfo;
+use crate::downloader::extractor::ExtractorRegistry;
+use crate::downloader::manager::Downloader;
+use crate::downloader::progress::ProgressManager;
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use std::sync::Arc;
+use url::Url;
+
+pub struct PageStrategy {
+    extractors: ExtractorRegistry,
+}
+
+impl PageStrategy {
+    pub fn new(extractors: ExtractorRegistry) -> Self {
+        Self { extractors }
+    }
+}
+
+impl DownloadStrategy for PageStrategy {
+    fn name(&self) -> &'static str {
+        "page"
+    }
+
+    fn probe(&self, probe: &Probe) -> bool {
+        probe.kind == StreamKind::Page
+    }
+
+    fn plan<'a>(
+        &'a self,
+        _downloader: &'a Downloader,
+        _probe: &'a Probe,
+    ) -> BoxFuture<'a, Result<Vec<Plan>>> {
+        // Every extracted media URL is planned by the strategy of its own.
+        Box::pin(async { Ok(Vec::new()) })
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        downloader: &'a Downloader,
+        probe: &'a Probe,
+        _plans: &'a [Plan],
+        _progress_manager: Arc<ProgressManager>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            // A page without media is saved as it is.
+            let page_url = Url::parse(&probe.url)?;
+            let Some(extractor) = self.extractors.find(&page_url) else {
+                return downloader.download_file(probe).await;
+            };
+
+            let get_response = downloader
+                .client()
+                .get_page(&probe.url, probe.headers.as_ref())
+                .await?;
+            let html = get_response.text().await.unwrap_or_default();
+            let media = extractor
+                .extract(downloader.client(), &page_url, &html)
+                .await?;
+            if media.is_empty() {
+                eprintln!(
+                    "{} extractor found no media in {}, saving the page",
+                    extractor.name(),
+                    probe.url
+                );
+                return downloader.download_file(probe).await;
+            }
+
+            let downloader = downloader.nested()?;
+            let total = media.len();
+            let mut failed = 0;
+            for item in media {
+                // Page headers such as cookies still apply, extractor headers win.
+                let mut headers = probe.headers.clone().unwrap_or_default();
+                headers.extend(item.headers);
+                let info = DownloadInfo {
+                    url: item.url,
+                    headers: Some(headers),
+                    filename: item.filename,
+                    dir: probe.output_dir(),
+                    sink: probe.sink.clone(),
+                    ..DownloadInfo::default()
+                };
+                // A candidate may be a dead link or an embed page, the others still count.
+                if let Err(e) = downloader.download_media(&info).await {
+                    eprintln!("Skipping {} found in {}: {}", info.url, probe.url, e);
+                    failed += 1;
+                }
+            }
+
+            if failed == total {
+                return Err(anyhow!(
+                    "None of the {} media in {} downloaded",
+                    total,
+                    probe.url
+                ));
+            }
+            Ok(())
+        })
+    }
+}
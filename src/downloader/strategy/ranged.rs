@@ -0,0 +1,45 @@
+use super::{DownloadStrategy, Plan, Probe};
+use crate::downloader::detector::StreamKind;
+use crate::downloader::manager::Downloader;
+use crate::downloader::segment::Segment;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use std::cmp::min;
+use std::sync::Arc;
+
+pub struct RangedHttpStrategy;
+
+impl DownloadStrategy for RangedHttpStrategy {
+    fn name(&self) -> &'static str {
+        "ranged"
+    }
+
+    fn probe(&self, probe: &Probe) -> bool {
+        probe.kind == StreamKind::File && probe.accept_ranges && probe.content_length.is_some()
+    }
+
+    fn plan<'a>(
+        &'a self,
+        downloader: &'a Downloader,
+        probe: &'a Probe,
+    ) -> BoxFuture<'a, Result<Vec<Plan>>> {
+        Box::pin(async move {
+            let content_length = probe.content_length.unwrap_or_default();
+            let segment_size = downloader.segment_size();
+            let url_arc = Arc::new(probe.url.clone());
+            let mut segments = Vec::new();
+
+            for offset in (0..content_length).step_by(segment_size as usize) {
+                let end = min(offset + segment_size - 1, content_length - 1);
+                let segment = Segment::new(url_arc.clone(), offset, end);
+                segments.push(segment);
+            }
+
+            Ok(vec![Plan {
+                filename: probe.filename.clone(),
+                segments,
+                ranged: true,
+            }])
+        })
+    }
+}
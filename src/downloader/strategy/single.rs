@@ -0,0 +1,96 @@
+use super::{DownloadStrategy, Plan, Probe};
+use crate::downloader::detector::StreamKind;
+use crate::downloader::manager::Downloader;
+use crate::downloader::progress::ProgressManager;
+use crate::downloader::segment::Segment;
+use crate::downloader::storage::{FileStorage, Storage};
+use anyhow::Result;
+use futures::future::BoxFuture;
+use std::sync::Arc;
+
+pub struct SingleStreamStrategy;
+
+impl DownloadStrategy for SingleStreamStrategy {
+    fn name(&self) -> &'static str {
+        "single"
+    }
+
+    fn probe(&self, probe: &Probe) -> bool {
+        probe.kind == StreamKind::File
+    }
+
+    fn plan<'a>(
+        &'a self,
+        _downloader: &'a Downloader,
+        probe: &'a Probe,
+    ) -> BoxFuture<'a, Result<Vec<Plan>>> {
+        Box::pin(async move {
+            let segments = match probe.content_length {
+                Some(content_length) if content_length > 0 => vec![Segment::new(
+                    Arc::new(probe.url.clone()),
+                    0,
+                    content_length - 1,
+                )],
+                _ => Vec::new(),
+            };
+            Ok(vec![Plan {
+                filename: probe.filename.clone(),
+                segments,
+                ranged: false,
+            }])
+        })
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        downloader: &'a Downloader,
+        probe: &'a Probe,
+        plans: &'a [Plan],
+        progress_manager: Arc<ProgressManager>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            for plan in plans {
+                let output = downloader.output().reserve(
+                    &probe.url,
+                    probe.output_dir().as_deref(),
+                    &plan.filename,
+                )?;
+                let result = async {
+                    let storage = Arc::new(FileStorage::create(&output.part_path)?);
+                    downloader
+                        .download_full(
+                            &probe.url,
+                            plan.expected_size(),
+                            storage,
+                            progress_manager.clone(),
+                        )
+                        .await?;
+                    output.commit(downloader.fsync_policy())
+                }
+                .await;
+                if let Err(e) = result {
+                    output.discard();
+                    return Err(e);
+                }
+                downloader.upload(&output.path).await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn stream<'a>(
+        &'a self,
+        downloader: &'a Downloader,
+        probe: &'a Probe,
+        plan: &'a Plan,
+        storage: Arc<dyn Storage>,
+        progress_manager: Arc<ProgressManager>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(downloader.download_full(
+            &probe.url,
+            plan.expected_size(),
+            storage,
+            progress_manager,
+        ))
+    }
+}
@@ -0,0 +1,62 @@
+use super::output::sanitize_filename;
+use chrono::Local;
+use url::Url;
+
+// Values for the placeholders of output name templates, e.g. "{host}/{date}/{filename}".
+// "{n}" is left untouched here, it is resolved while reserving a unique name.
+pub struct TemplateContext {
+    host: String,
+    date: String,
+    filename: String,
+    title: String,
+    ext: String,
+}
+
+impl TemplateContext {
+    pub fn new(url: &str, filename: &str) -> Self {
+        let filename = sanitize_filename(filename);
+        let (title, ext) = match filename.rsplit_once('.') {
+            Some((title, ext)) if !title.is_empty() => (title.to_string(), ext.to_string()),
+            _ => (filename.clone(), String::new()),
+        };
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(sanitize_filename))
+            .unwrap_or_default();
+
+        Self {
+            host,
+            date: Local::now().format("%Y-%m-%d").to_string(),
+            filename,
+            title,
+            ext,
+        }
+    }
+
+    pub fn render(&self, template: &str) -> String {
+        let mut result = String::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            result.push_str(&rest[..start]);
+            let Some(end) = rest[start..].find('}') else {
+                break;
+            };
+            let placeholder = &rest[start..start + end + 1];
+            let value = match &placeholder[1..placeholder.len() - 1] {
+                "host" => &self.host,
+                "date" => &self.date,
+                "filename" => &self.filename,
+                "title" => &self.title,
+                "ext" => &self.ext,
+                // Unknown placeholders and "{n}" are kept as written.
+                _ => placeholder,
+            };
+            result.push_str(value);
+            rest = &rest[start + end + 1..];
+        }
+        result.push_str(rest);
+
+        result
+    }
+}
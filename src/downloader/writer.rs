@@ -0,0 +1,112 @@
+use super::constant;
+use super::storage::Storage;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FsyncPolicy {
+    Never,
+    #[default]
+    OnComplete,
+    PerSegment,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WriterOptions {
+    pub buffer_size: usize,
+    pub memory_budget: usize,
+    pub fsync_policy: FsyncPolicy,
+}
+
+impl Default for WriterOptions {
+    fn default() -> Self {
+        Self {
+            buffer_size: constant::WRITE_BUFFER_SIZE,
+            memory_budget: constant::WRITE_MEMORY_BUDGET,
+            fsync_policy: FsyncPolicy::default(),
+        }
+    }
+}
+
+pub struct Writer {
+    storage: Arc<dyn Storage>,
+    buffer_size: usize,
+    fsync_policy: FsyncPolicy,
+    buffers: Semaphore,
+}
+
+impl Writer {
+    pub fn new(storage: Arc<dyn Storage>, options: &WriterOptions) -> Self {
+        let buffer_size = options.buffer_size.max(1);
+        Self {
+            storage,
+            buffer_size,
+            fsync_policy: options.fsync_policy,
+            buffers: Semaphore::new((options.memory_budget / buffer_size).max(1)),
+        }
+    }
+
+    pub fn segment(&self, start: u64) -> SegmentWriter<'_> {
+        SegmentWriter {
+            writer: self,
+            offset: start,
+            buffer: Vec::new(),
+            permit: None,
+        }
+    }
+}
+
+pub struct SegmentWriter<'a> {
+    writer: &'a Writer,
+    offset: u64,
+    buffer: Vec<u8>,
+    permit: Option<SemaphorePermit<'a>>,
+}
+
+impl SegmentWriter<'_> {
+    pub fn write(&mut self, chunk: &[u8]) -> Result<()> {
+        // Buffers are shared within the memory budget, a segment without one writes through.
+        if self.permit.is_none() {
+            self.permit = self.writer.buffers.try_acquire().ok();
+            if self.permit.is_some() {
+                self.buffer.reserve_exact(self.writer.buffer_size);
+            }
+        }
+
+        if self.permit.is_none() {
+            self.writer.storage.write_all_at(chunk, self.offset)?;
+            self.offset += chunk.len() as u64;
+            return Ok(());
+        }
+
+        self.buffer.extend_from_slice(chunk);
+        if self.buffer.len() >= self.writer.buffer_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        self.flush()?;
+        if self.writer.fsync_policy == FsyncPolicy::PerSegment {
+            self.writer.storage.sync_data()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        self.writer
+            .storage
+            .write_all_at(&self.buffer, self.offset)?;
+        self.offset += self.buffer.len() as u64;
+        self.buffer.clear();
+        Ok(())
+    }
+}
@@ -1,5 +1,12 @@
+pub mod auth;
 pub mod client;
 mod constant;
+pub mod content_disposition;
+pub mod cookie_jar;
+pub mod header_profile;
+pub mod proxy;
 pub mod response;
+pub mod tls;
+pub mod tor;
 pub mod user_agent;
 mod encoding;
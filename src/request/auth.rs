@@ -0,0 +1,279 @@
+use super::constant;
+use anyhow::{anyhow, Result};
+use digest_auth::{AuthContext, HttpMethod, WwwAuthenticateHeader};
+use reqwest::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use reqwest::{Method, RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    // A netrc file, read for every job so edits apply without a restart.
+    pub netrc: Option<String>,
+    pub credentials: Vec<Credential>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Credential {
+    // "host" or "host:port", a credential without a host applies to the host of the job.
+    pub host: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // Sent as a Bearer token up front, without waiting for a challenge.
+    pub token: Option<String>,
+}
+
+impl Credential {
+    fn matches(&self, url: &Url) -> bool {
+        let Some(host) = &self.host else {
+            return false;
+        };
+        let Some(url_host) = url.host_str() else {
+            return false;
+        };
+        let host = host.to_ascii_lowercase();
+        host == url_host
+            || url
+                .port_or_known_default()
+                .is_some_and(|port| host == format!("{}:{}", url_host, port))
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Challenge {
+    Basic,
+    Digest(WwwAuthenticateHeader),
+}
+
+impl Challenge {
+    // Digest is preferred when a server offers both schemes.
+    fn from_response(response: &Response) -> Option<Self> {
+        let challenges: Vec<&str> = response
+            .headers()
+            .get_all(WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect();
+        let digest = challenges
+            .iter()
+            .filter(|challenge| has_scheme(challenge, constant::DIGEST_SCHEME))
+            .find_map(|challenge| WwwAuthenticateHeader::parse(challenge).ok());
+        match digest {
+            Some(digest) => Some(Challenge::Digest(digest)),
+            None => challenges
+                .iter()
+                .any(|challenge| has_scheme(challenge, constant::BASIC_SCHEME))
+                .then_some(Challenge::Basic),
+        }
+    }
+}
+
+// Credentials are matched by host. The first match wins, so job credentials go first,
+// then the config and at last the netrc file.
+#[derive(Clone, Debug, Default)]
+pub struct CredentialStore {
+    credentials: Vec<Credential>,
+    // The last challenge of each host, so later requests authenticate without a 401 first.
+    challenges: Arc<Mutex<HashMap<String, Challenge>>>,
+}
+
+impl CredentialStore {
+    // Credentials without a host are bound to the host of the job, so they never reach the
+    // CDNs, mirrors or media hosts the job leads to.
+    pub fn load(
+        url: &str,
+        job_credential: Option<Credential>,
+        config: &AuthConfig,
+    ) -> Result<Self> {
+        let mut credentials: Vec<Credential> = job_credential.into_iter().collect();
+        credentials.extend(config.credentials.iter().cloned());
+        if let Some(netrc) = &config.netrc {
+            let data = fs::read_to_string(netrc)
+                .map_err(|e| anyhow!("Failed to read netrc file {}: {}", netrc, e))?;
+            credentials.extend(parse_netrc(&data));
+        }
+        let job_host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(String::from));
+        for credential in credentials.iter_mut().filter(|c| c.host.is_none()) {
+            credential.host = job_host.clone();
+        }
+        Ok(Self {
+            credentials,
+            challenges: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    pub fn find(&self, url: &Url) -> Option<&Credential> {
+        self.credentials.iter().find(|c| c.matches(url))
+    }
+
+    // Stores the challenge of a 401 response, false when there is nothing to retry with.
+    pub fn learn(&self, url: &Url, response: &Response) -> bool {
+        let (Some(host), Some(challenge)) = (url.host_str(), Challenge::from_response(response))
+        else {
+            return false;
+        };
+        self.challenges
+            .lock()
+            .unwrap()
+            .insert(host.to_string(), challenge);
+        true
+    }
+
+    pub fn authorize(
+        &self,
+        request: RequestBuilder,
+        method: &Method,
+        url: &Url,
+        credential: &Credential,
+    ) -> Result<RequestBuilder> {
+        if let Some(token) = &credential.token {
+            return Ok(request.bearer_auth(token));
+        }
+        let username = credential.username.as_deref().unwrap_or_default();
+        let password = credential.password.as_deref().unwrap_or_default();
+
+        let mut challenges = self.challenges.lock().unwrap();
+        match url.host_str().and_then(|host| challenges.get_mut(host)) {
+            Some(Challenge::Basic) => Ok(request.basic_auth(username, Some(password))),
+            Some(Challenge::Digest(challenge)) => {
+                let uri = match url.query() {
+                    Some(query) => format!("{}?{}", url.path(), query),
+                    None => url.path().to_string(),
+                };
+                let context = AuthContext::new_with_method(
+                    username,
+                    password,
+                    uri,
+                    None::<&[u8]>,
+                    HttpMethod::from(method.as_str()),
+                );
+                let authorization = challenge
+                    .respond(&context)
+                    .map_err(|e| anyhow!("Failed to answer digest challenge: {}", e))?;
+                Ok(request.header(AUTHORIZATION, authorization.to_header_string()))
+            }
+            None => Ok(request),
+        }
+    }
+}
+
+fn has_scheme(challenge: &str, scheme: &str) -> bool {
+    challenge
+        .split_whitespace()
+        .next()
+        .is_some_and(|s| s.eq_ignore_ascii_case(scheme))
+}
+
+// Supports machine, default, login and password. Macro definitions run until the next blank line.
+fn parse_netrc(data: &str) -> Vec<Credential> {
+    let mut credentials: Vec<Credential> = Vec::new();
+    let mut in_macro = false;
+
+    for line in data.lines() {
+        if in_macro {
+            in_macro = !line.trim().is_empty();
+            continue;
+        }
+        let mut tokens = line.split_whitespace();
+        while let Some(token) = tokens.next() {
+            match token {
+                "machine" => credentials.push(Credential {
+                    host: tokens.next().map(String::from),
+                    ..Credential::default()
+                }),
+                "default" => credentials.push(Credential::default()),
+                "login" | "password" | "account" => {
+                    let value = tokens.next().map(String::from);
+                    if let Some(credential) = credentials.last_mut() {
+                        match token {
+                            "login" => credential.username = value,
+                            "password" => credential.password = value,
+                            _ => {}
+                        }
+                    }
+                }
+                "macdef" => {
+                    in_macro = true;
+                    break;
+                }
+                _ if token.starts_with('#') => break,
+                _ => {}
+            }
+        }
+    }
+
+    // The default entry only applies when no machine matched.
+    credentials.sort_by_key(|credential| credential.host.is_none());
+    credentials
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    #[test]
+    fn credentials_without_host_stay_on_the_job_host() {
+        let config = AuthConfig {
+            netrc: None,
+            credentials: vec![Credential {
+                token: Some("secret".to_string()),
+                ..Credential::default()
+            }],
+        };
+        let store = CredentialStore::load("https://example.com/video", None, &config).unwrap();
+
+        assert!(store
+            .find(&url("https://example.com/segment/1.ts"))
+            .is_some());
+        assert!(store.find(&url("https://cdn.example.net/1.ts")).is_none());
+    }
+
+    #[test]
+    fn job_credentials_win_over_config() {
+        let config = AuthConfig {
+            netrc: None,
+            credentials: vec![Credential {
+                host: Some("example.com".to_string()),
+                username: Some("config".to_string()),
+                ..Credential::default()
+            }],
+        };
+        let job = Credential {
+            username: Some("job".to_string()),
+            ..Credential::default()
+        };
+        let store = CredentialStore::load("https://example.com/", Some(job), &config).unwrap();
+
+        let credential = store.find(&url("https://example.com/file")).unwrap();
+        assert_eq!(credential.username.as_deref(), Some("job"));
+    }
+
+    #[test]
+    fn parses_netrc_with_default_last() {
+        let credentials = parse_netrc(
+            "default login anonymous password guest\n\
+             machine example.com:8443\n  login user\n  password pass # comment\n\
+             macdef init\ncd /pub\n\n\
+             machine other.org login other password secret\n",
+        );
+
+        let hosts: Vec<_> = credentials.iter().map(|c| c.host.as_deref()).collect();
+        assert_eq!(hosts, [Some("example.com:8443"), Some("other.org"), None]);
+        assert_eq!(credentials[0].username.as_deref(), Some("user"));
+        assert_eq!(credentials[0].password.as_deref(), Some("pass"));
+        assert_eq!(credentials[2].username.as_deref(), Some("anonymous"));
+        assert!(credentials[0].matches(&url("https://example.com:8443/file")));
+        assert!(!credentials[0].matches(&url("https://example.com/file")));
+    }
+}
@@ -1,39 +1,111 @@
-use super::constant::TOR_PROXY_SCHEME;
+use super::auth::CredentialStore;
+use super::cookie_jar::SharedCookieJar;
+use super::header_profile::HeaderProfile;
+use super::proxy::ProxyConfig;
 use super::response::Response;
-use super::user_agent::UserAgent;
-use anyhow::Result;
-use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
-use reqwest::{Client as ReqwestClient, Proxy};
+use super::tls::TlsConfig;
+use anyhow::{anyhow, Result};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use reqwest::{Client as ReqwestClient, Method, Response as ReqwestResponse, StatusCode};
 use std::collections::HashMap;
 use std::fmt;
+use std::sync::{Arc, RwLock};
+use url::Url;
 
 #[derive(Clone, Debug)]
 pub struct Client {
-    inner: ReqwestClient,
+    circuit: usize,
+    circuits: Arc<RwLock<Vec<ReqwestClient>>>,
+    settings: Arc<ClientSettings>,
+    page_headers: HeaderMap,
+    fetch_headers: HeaderMap,
     default_headers: HashMap<String, String>,
+    credentials: CredentialStore,
+}
+
+// Kept to build the clients again once Tor switched to new circuits.
+#[derive(Debug)]
+struct ClientSettings {
+    proxy: ProxyConfig,
+    header_profile: HeaderProfile,
+    tls: TlsConfig,
+    cookie_jar: SharedCookieJar,
+}
+
+impl ClientSettings {
+    fn build(&self) -> Result<Vec<ReqwestClient>> {
+        self.proxy
+            .isolated()
+            .iter()
+            .map(|proxy| {
+                let client_builder = self
+                    .header_profile
+                    .apply(ReqwestClient::builder())
+                    .cookie_provider(self.cookie_jar.clone());
+                let client_builder = self.tls.apply(client_builder)?;
+                Ok(proxy.apply(client_builder)?.build()?)
+            })
+            .collect()
+    }
 }
 
 impl Client {
-    pub fn new(use_tor: bool, user_agent: &UserAgent) -> Result<Self> {
-        let mut client_builder = ReqwestClient::builder().user_agent(user_agent.clone());
-        if use_tor {
-            let proxy = Proxy::all(TOR_PROXY_SCHEME)?;
-            client_builder = client_builder.proxy(proxy);
-        }
-        let client = client_builder.build()?;
+    pub fn new(
+        proxy: &ProxyConfig,
+        header_profile: &HeaderProfile,
+        tls: &TlsConfig,
+        cookie_jar: &SharedCookieJar,
+    ) -> Result<Self> {
+        let settings = ClientSettings {
+            proxy: proxy.clone(),
+            header_profile: header_profile.clone(),
+            tls: tls.clone(),
+            cookie_jar: cookie_jar.clone(),
+        };
         Ok(Self {
-            inner: client,
+            circuit: 0,
+            circuits: Arc::new(RwLock::new(settings.build()?)),
+            settings: Arc::new(settings),
+            page_headers: header_profile.page_header_map()?,
+            fetch_headers: header_profile.fetch_header_map()?,
             default_headers: HashMap::new(),
+            credentials: CredentialStore::default(),
         })
     }
 
+    pub fn with_credentials(mut self, credentials: CredentialStore) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    // A client bound to one of the isolated circuits, picked round robin.
+    pub fn circuit(&self, index: usize) -> Self {
+        Self {
+            circuit: index,
+            ..self.clone()
+        }
+    }
+
+    // Pooled connections stay on their old circuits, so every client of the job is built
+    // again with new isolation credentials.
+    pub fn renew_circuits(&self) -> Result<()> {
+        let circuits = self.settings.build()?;
+        *self.circuits.write().unwrap() = circuits;
+        Ok(())
+    }
+
+    fn inner(&self) -> ReqwestClient {
+        let circuits = self.circuits.read().unwrap();
+        circuits[self.circuit % circuits.len()].clone()
+    }
+
     pub async fn head(
         &self,
         url: &str,
         headers: Option<&HashMap<String, String>>,
     ) -> Result<Response> {
-        let headers = self.convert_headers(headers);
-        let response = self.inner.head(url).headers(headers).send().await?;
+        let headers = self.convert_headers(&self.fetch_headers, headers);
+        let response = self.send(Method::HEAD, url, headers).await?;
         Ok(Response::new(response))
     }
 
@@ -42,13 +114,69 @@ impl Client {
         url: &str,
         headers: Option<&HashMap<String, String>>,
     ) -> Result<Response> {
-        let headers = self.convert_headers(headers);
-        let response = self.inner.get(url).headers(headers).send().await?;
+        let headers = self.convert_headers(&self.fetch_headers, headers);
+        let response = self.send(Method::GET, url, headers).await?;
+        Ok(Response::new(response))
+    }
+
+    // Loads a page the way a browser navigates to it.
+    pub async fn get_page(
+        &self,
+        url: &str,
+        headers: Option<&HashMap<String, String>>,
+    ) -> Result<Response> {
+        let headers = self.convert_headers(&self.page_headers, headers);
+        let response = self.send(Method::GET, url, headers).await?;
         Ok(Response::new(response))
     }
 
-    fn convert_headers(&self, extra_headers: Option<&HashMap<String, String>>) -> HeaderMap {
-        let mut header_map = HeaderMap::new();
+    // A 401 is answered once with the matching credential, an Authorization header given by
+    // the job is sent as is.
+    async fn send(&self, method: Method, url: &str, headers: HeaderMap) -> Result<ReqwestResponse> {
+        let parsed_url = Url::parse(url)?;
+        let credential = self
+            .credentials
+            .find(&parsed_url)
+            .filter(|_| !headers.contains_key(AUTHORIZATION));
+        let Some(credential) = credential else {
+            return Ok(self
+                .inner()
+                .request(method, url)
+                .headers(headers)
+                .send()
+                .await?);
+        };
+
+        let inner = self.inner();
+        let mut retried = false;
+        loop {
+            let request = inner.request(method.clone(), url).headers(headers.clone());
+            let request = self
+                .credentials
+                .authorize(request, &method, &parsed_url, credential)?;
+            let response = request.send().await?;
+            if response.status() != StatusCode::UNAUTHORIZED {
+                return Ok(response);
+            }
+            // The error page of a rejected credential must not be saved as the file.
+            if retried
+                || credential.token.is_some()
+                || !self.credentials.learn(&parsed_url, &response)
+            {
+                return Err(anyhow!("Authentication failed for {}", url));
+            }
+            retried = true;
+        }
+    }
+
+    // reqwest sends the headers of a request before its own defaults, so the profile headers
+    // start every request to keep their order.
+    fn convert_headers(
+        &self,
+        profile_headers: &HeaderMap,
+        extra_headers: Option<&HashMap<String, String>>,
+    ) -> HeaderMap {
+        let mut header_map = profile_headers.clone();
 
         for (key, value) in &self.default_headers {
             if let Ok(header_name) = HeaderName::from_bytes(key.as_bytes()) {
@@ -77,3 +205,95 @@ impl fmt::Display for Client {
         write!(f, "Client()")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::cookie_jar::CookieJar;
+    use crate::request::user_agent::UserAgent;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // Answers every request with an empty page and hands out the raw request heads.
+    async fn serve() -> (String, tokio::sync::mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let (heads, received) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let heads = heads.clone();
+                tokio::spawn(async move {
+                    let mut head = Vec::new();
+                    let mut buf = [0u8; 1024];
+                    while !head.ends_with(b"\r\n\r\n") {
+                        match socket.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => head.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let _ = heads.send(String::from_utf8_lossy(&head).to_string());
+                    let _ = socket
+                        .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                        .await;
+                });
+            }
+        });
+        (url, received)
+    }
+
+    fn client(user_agent: UserAgent) -> Client {
+        let proxy = ProxyConfig {
+            use_env: false,
+            ..ProxyConfig::default()
+        };
+        Client::new(
+            &proxy,
+            &user_agent.header_profile(&HashMap::new()).unwrap(),
+            &TlsConfig::default(),
+            &Arc::new(CookieJar::default()),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn sends_navigation_headers_only_for_pages() {
+        let (url, mut heads) = serve().await;
+        let client = client(UserAgent::Chrome);
+
+        client.get_page(&url, None).await.unwrap();
+        let page = heads.recv().await.unwrap();
+        assert!(page.contains("\r\nsec-fetch-mode: navigate\r\n"));
+        assert!(page.contains("\r\nupgrade-insecure-requests: 1\r\n"));
+
+        client.head(&url, None).await.unwrap();
+        client.get(&url, None).await.unwrap();
+        for fetch in [heads.recv().await.unwrap(), heads.recv().await.unwrap()] {
+            assert!(fetch.contains("\r\nsec-fetch-mode: cors\r\n"));
+            assert!(fetch.contains("\r\naccept: */*\r\n"));
+            assert!(fetch.contains("\r\nsec-ch-ua: "));
+            assert!(!fetch.contains("navigate") && !fetch.contains("upgrade-insecure-requests"));
+        }
+    }
+
+    #[tokio::test]
+    async fn title_cases_firefox_headers() {
+        let (url, mut heads) = serve().await;
+
+        client(UserAgent::Firefox).head(&url, None).await.unwrap();
+        let head = heads.recv().await.unwrap();
+        assert!(head.contains("\r\nUser-Agent: "));
+        assert!(head.contains("\r\nSec-Fetch-Dest: empty\r\n"));
+    }
+
+    #[test]
+    fn rejects_title_cased_client_hints() {
+        let profile = HeaderProfile::new(&[("sec-ch-ua-mobile", "?0")], &[], true);
+        assert!(profile.validate().is_err());
+        let profile = HeaderProfile::new(&[("sec-ch-ua-mobile", "?0")], &[], false);
+        assert!(profile.validate().is_ok());
+        for user_agent in [UserAgent::Chrome, UserAgent::Firefox] {
+            let profile = user_agent.header_profile(&HashMap::new()).unwrap();
+            assert!(profile.validate().is_ok());
+        }
+    }
+}
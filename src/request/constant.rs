@@ -1,7 +1,75 @@
+use std::time::Duration;
+
 pub const TOR_PROXY_SCHEME: &str = "socks5h://127.0.0.1:9050";
+pub const TOR_CONTROL_ADDRESS: &str = "127.0.0.1:9051";
+pub const TOR_CONTROL_TIMEOUT: Duration = Duration::from_secs(10);
+pub const TOR_NEWNYM_AFTER_FAILURES: u32 = 3;
+pub const ISOLATION_USERNAME: &str = "hermesdl";
+pub const PROXY_SCHEMES: [&str; 6] = ["http", "https", "socks4", "socks4a", "socks5", "socks5h"];
 pub const FIREFOX: &str = "FireFox";
 pub const CHROME: &str = "Chrome";
 pub const FIREFOX_USER_AGENT: &str =
     "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:109.0) Gecko/20100101 Firefox/113.0";
-pub const CHROME_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/125.0.0.0 Safari/537.36 GLS/100.10.9939.100";
+pub const CHROME_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/125.0.0.0 Safari/537.36";
+pub const USER_AGENT_HEADER: &str = "User-Agent";
+// A top-level navigation as the browsers send it, used to load pages. Accept-Encoding is left
+// out, the body is written as received.
+pub const FIREFOX_PAGE_HEADERS: [(&str, &str); 8] = [
+    (USER_AGENT_HEADER, FIREFOX_USER_AGENT),
+    (
+        "Accept",
+        "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,*/*;q=0.8",
+    ),
+    ("Accept-Language", "en-US,en;q=0.5"),
+    ("Upgrade-Insecure-Requests", "1"),
+    ("Sec-Fetch-Dest", "document"),
+    ("Sec-Fetch-Mode", "navigate"),
+    ("Sec-Fetch-Site", "none"),
+    ("Sec-Fetch-User", "?1"),
+];
+// A fetch made by a player script, used for files, ranges, manifests and segments.
+pub const FIREFOX_FETCH_HEADERS: [(&str, &str); 6] = [
+    (USER_AGENT_HEADER, FIREFOX_USER_AGENT),
+    ("Accept", "*/*"),
+    ("Accept-Language", "en-US,en;q=0.5"),
+    ("Sec-Fetch-Dest", "empty"),
+    ("Sec-Fetch-Mode", "cors"),
+    ("Sec-Fetch-Site", "cross-site"),
+];
+pub const CHROME_CLIENT_HINTS: &str =
+    "\"Google Chrome\";v=\"125\", \"Chromium\";v=\"125\", \"Not.A/Brand\";v=\"24\"";
+pub const CHROME_PAGE_HEADERS: [(&str, &str); 11] = [
+    ("sec-ch-ua", CHROME_CLIENT_HINTS),
+    ("sec-ch-ua-mobile", "?0"),
+    ("sec-ch-ua-platform", "\"Windows\""),
+    ("upgrade-insecure-requests", "1"),
+    ("user-agent", CHROME_USER_AGENT),
+    ("accept", "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,image/apng,*/*;q=0.8,application/signed-exchange;v=b3;q=0.7"),
+    ("sec-fetch-site", "none"),
+    ("sec-fetch-mode", "navigate"),
+    ("sec-fetch-user", "?1"),
+    ("sec-fetch-dest", "document"),
+    ("accept-language", "en-US,en;q=0.9"),
+];
+pub const CHROME_FETCH_HEADERS: [(&str, &str); 9] = [
+    ("sec-ch-ua-platform", "\"Windows\""),
+    ("user-agent", CHROME_USER_AGENT),
+    ("sec-ch-ua", CHROME_CLIENT_HINTS),
+    ("sec-ch-ua-mobile", "?0"),
+    ("accept", "*/*"),
+    ("sec-fetch-site", "cross-site"),
+    ("sec-fetch-mode", "cors"),
+    ("sec-fetch-dest", "empty"),
+    ("accept-language", "en-US,en;q=0.9"),
+];
+// Browsers always send client hints in lower case.
+pub const CLIENT_HINT_PREFIX: &str = "sec-ch-";
+pub const NETSCAPE_HEADER: &str = "# Netscape HTTP Cookie File\n";
+pub const NETSCAPE_HTTP_ONLY_PREFIX: &str = "#HttpOnly_";
+pub const JSON_EXTENSION: &str = "json";
+pub const BASIC_SCHEME: &str = "Basic";
+pub const DIGEST_SCHEME: &str = "Digest";
+pub const PIN_PREFIX: &str = "sha256/";
+pub const PIN_HASH_LENGTH: usize = 32;
+pub const ALPN_PROTOCOLS: [&[u8]; 2] = [b"h2", b"http/1.1"];
 pub const USER_AGENT_PARSE_ERROR: &str = "Failed to parsing UserAgent";
@@ -0,0 +1,126 @@
+use percent_encoding::percent_decode_str;
+
+// Extracts the file name from a Content-Disposition header value (RFC 6266),
+// preferring the RFC 5987 "filename*" parameter over the plain "filename".
+pub fn parse_filename(header: &str) -> Option<String> {
+    let mut filename = None;
+    let mut extended_filename = None;
+
+    for (name, value) in parse_parameters(header) {
+        match name.to_ascii_lowercase().as_str() {
+            "filename*" => {
+                if let Some(decoded) = decode_extended_value(&value) {
+                    extended_filename = Some(decoded);
+                }
+            }
+            "filename" => filename = Some(value),
+            _ => {}
+        }
+    }
+
+    extended_filename
+        .or(filename)
+        .filter(|filename| !filename.trim().is_empty())
+}
+
+fn parse_parameters(header: &str) -> Vec<(String, String)> {
+    let mut parameters = Vec::new();
+    let mut chars = header.chars().peekable();
+
+    // Skip the disposition type ("inline", "attachment", ...), some servers leave it out.
+    let first_token = header.split(';').next().unwrap_or_default();
+    if !first_token.contains('=') {
+        for c in chars.by_ref() {
+            if c == ';' {
+                break;
+            }
+        }
+    }
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace() || *c == ';').is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut name = String::new();
+        while let Some(c) = chars.next_if(|c| *c != '=' && *c != ';') {
+            name.push(c);
+        }
+        if chars.next_if_eq(&'=').is_none() {
+            continue;
+        }
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            // quoted-string, a backslash escapes the following character
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => {
+                        if let Some(escaped) = chars.next() {
+                            value.push(escaped);
+                        }
+                    }
+                    '"' => break,
+                    _ => value.push(c),
+                }
+            }
+            while chars.next_if(|c| *c != ';').is_some() {}
+        } else {
+            while let Some(c) = chars.next_if(|c| *c != ';') {
+                value.push(c);
+            }
+            value = value.trim_end().to_string();
+        }
+
+        parameters.push((name.trim().to_string(), value));
+    }
+
+    parameters
+}
+
+fn decode_extended_value(value: &str) -> Option<String> {
+    // charset'language'percent-encoded-value
+    let mut parts = value.splitn(3, '\'');
+    let charset = parts.next()?.trim().to_ascii_lowercase();
+    let _language = parts.next()?;
+    let encoded = parts.next()?;
+    let bytes: Vec<u8> = percent_decode_str(encoded).collect();
+
+    match charset.as_str() {
+        "utf-8" => String::from_utf8(bytes).ok(),
+        // ISO-8859-1 maps every byte to the code point of the same value.
+        "iso-8859-1" => Some(bytes.into_iter().map(char::from).collect()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_filenames() {
+        let cases = [
+            ("attachment; filename=\"a.pdf\"", Some("a.pdf")),
+            ("filename=\"a.pdf\"", Some("a.pdf")),
+            ("filename=a.pdf", Some("a.pdf")),
+            ("filename=\"a;b.pdf\"; size=3", Some("a;b.pdf")),
+            (
+                "inline; filename=\"a \\\"quoted\\\".pdf\"",
+                Some("a \"quoted\".pdf"),
+            ),
+            (
+                "attachment; filename=\"fallback.pdf\"; filename*=UTF-8''%E2%82%AC%20rates.pdf",
+                Some("€ rates.pdf"),
+            ),
+            ("attachment; filename*=iso-8859-1'en'%A3.txt", Some("£.txt")),
+            ("attachment", None),
+            ("attachment; filename=\"  \"", None),
+        ];
+        for (header, expected) in cases {
+            assert_eq!(parse_filename(header).as_deref(), expected, "{}", header);
+        }
+    }
+}
@@ -0,0 +1,375 @@
+use super::constant;
+use anyhow::{anyhow, Result};
+use chrono::DateTime;
+use cookie_store::{Cookie, CookieDomain, CookieExpiration, CookieStore, RawCookie};
+use reqwest::header::HeaderValue;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tempfile::NamedTempFile;
+use tokio::{fs, task};
+use url::Url;
+
+pub type SharedCookieJar = Arc<CookieJar>;
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CookieConfig {
+    // Loaded at startup, a .json file is read as JSON and anything else as Netscape cookies.txt.
+    pub file: Option<String>,
+    // Writes the jar back to the file after every job.
+    pub persist: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CookieFormat {
+    Netscape,
+    Json,
+}
+
+impl CookieFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(extension) if extension == constant::JSON_EXTENSION => CookieFormat::Json,
+            _ => CookieFormat::Netscape,
+        }
+    }
+
+    fn detect(data: &str) -> Self {
+        match data.trim_start().chars().next() {
+            Some('[') | Some('{') => CookieFormat::Json,
+            _ => CookieFormat::Netscape,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CookieJar {
+    store: RwLock<CookieStore>,
+}
+
+impl CookieJar {
+    // Cookies given as Set-Cookie values, as if the job URL had answered with them.
+    pub fn add(&self, url: &str, cookies: &[String]) -> Result<()> {
+        let url = Url::parse(url)?;
+        let mut store = self.store.write().unwrap();
+        for cookie in cookies {
+            store
+                .parse(cookie, &url)
+                .map_err(|e| anyhow!("Invalid cookie {}: {}", cookie, e))?;
+        }
+        Ok(())
+    }
+
+    // Imported cookies are merged into the jar, the number of imported cookies is returned.
+    pub fn import(&self, data: &str) -> Result<usize> {
+        match CookieFormat::detect(data) {
+            CookieFormat::Json => self.import_json(data),
+            CookieFormat::Netscape => self.import_netscape(data),
+        }
+    }
+
+    pub fn export(&self, format: CookieFormat) -> Result<String> {
+        let store = self.store.read().unwrap();
+        match format {
+            // Both formats keep session cookies, the jar is saved to carry them to later jobs.
+            CookieFormat::Json => {
+                let cookies: Vec<&Cookie> = store.iter_unexpired().collect();
+                Ok(serde_json::to_string_pretty(&cookies)?)
+            }
+            CookieFormat::Netscape => {
+                let mut data = String::from(constant::NETSCAPE_HEADER);
+                for cookie in store.iter_unexpired() {
+                    if let Some(line) = netscape_line(cookie) {
+                        data.push_str(&line);
+                        data.push('\n');
+                    }
+                }
+                Ok(data)
+            }
+        }
+    }
+
+    pub fn clear(&self) {
+        self.store.write().unwrap().clear();
+    }
+
+    pub async fn load(&self, path: &Path) -> Result<usize> {
+        let data = fs::read_to_string(path).await?;
+        self.import(&data)
+    }
+
+    // Jobs finishing together save at the same time, each one writes a temporary file of its
+    // own next to the target and renames it.
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        let data = self.export(CookieFormat::from_path(path))?;
+        let parent = match path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            Some(parent) => parent.to_path_buf(),
+            None => PathBuf::from("."),
+        };
+        fs::create_dir_all(&parent).await?;
+        let path = path.to_path_buf();
+        task::spawn_blocking(move || -> Result<()> {
+            let mut file = NamedTempFile::new_in(parent)?;
+            file.write_all(data.as_bytes())?;
+            file.persist(path)?;
+            Ok(())
+        })
+        .await?
+    }
+
+    fn import_json(&self, data: &str) -> Result<usize> {
+        let imported = cookie_store::serde::json::load(data.as_bytes()).map_err(|e| anyhow!(e))?;
+        let mut store = self.store.write().unwrap();
+        let mut count = 0;
+        for cookie in imported.iter_unexpired() {
+            let url = cookie_url(&cookie.domain, &cookie.path, cookie.secure())?;
+            store
+                .insert(cookie.clone(), &url)
+                .map_err(|e| anyhow!("Invalid cookie {}: {}", cookie.name(), e))?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    // Fields are tab separated: domain, include subdomains, path, secure, expiry, name and value.
+    fn import_netscape(&self, data: &str) -> Result<usize> {
+        let mut store = self.store.write().unwrap();
+        let mut count = 0;
+        for (index, line) in data.lines().enumerate() {
+            let (http_only, line) = match line.strip_prefix(constant::NETSCAPE_HTTP_ONLY_PREFIX) {
+                Some(line) => (true, line),
+                None => (false, line),
+            };
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.trim_end_matches(['\r', '\n']).split('\t').collect();
+            let [domain, include_subdomains, path, secure, expires, name, value] = fields[..]
+            else {
+                return Err(anyhow!("Invalid cookie in line {}", index + 1));
+            };
+
+            let domain = domain.trim_start_matches('.');
+            let secure = secure.eq_ignore_ascii_case("TRUE");
+            let mut cookie = format!("{}={}; Path={}", name, value, path);
+            if include_subdomains.eq_ignore_ascii_case("TRUE") {
+                cookie.push_str(&format!("; Domain={}", domain));
+            }
+            if secure {
+                cookie.push_str("; Secure");
+            }
+            if http_only {
+                cookie.push_str("; HttpOnly");
+            }
+            // An expiry of 0 marks a session cookie.
+            let expires: i64 = expires
+                .parse()
+                .map_err(|_| anyhow!("Invalid expiry in line {}", index + 1))?;
+            if expires > 0 {
+                let expires = DateTime::from_timestamp(expires, 0)
+                    .ok_or_else(|| anyhow!("Invalid expiry in line {}", index + 1))?;
+                cookie.push_str(&format!(
+                    "; Expires={}",
+                    expires.format("%a, %d %b %Y %H:%M:%S GMT")
+                ));
+            }
+
+            let url = cookie_url(
+                &CookieDomain::HostOnly(domain.to_string()),
+                path,
+                Some(secure),
+            )?;
+            store
+                .parse(&cookie, &url)
+                .map_err(|e| anyhow!("Invalid cookie in line {}: {}", index + 1, e))?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+impl reqwest::cookie::CookieStore for CookieJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        let cookies = cookie_headers
+            .filter_map(|value| value.to_str().ok())
+            .filter_map(|value| RawCookie::parse(value.to_string()).ok());
+        self.store
+            .write()
+            .unwrap()
+            .store_response_cookies(cookies, url);
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        let cookies = self
+            .store
+            .read()
+            .unwrap()
+            .get_request_values(url)
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        if cookies.is_empty() {
+            return None;
+        }
+        HeaderValue::from_str(&cookies).ok()
+    }
+}
+
+pub async fn create_shared_cookie_jar(config: &CookieConfig) -> Result<SharedCookieJar> {
+    let cookie_jar = CookieJar::default();
+    if let Some(file) = &config.file {
+        let path = Path::new(file);
+        if path.exists() {
+            let count = cookie_jar
+                .load(path)
+                .await
+                .map_err(|e| anyhow!("Failed to load cookies from {}: {}", file, e))?;
+            eprintln!("Loaded {} cookies from {}", count, file);
+        }
+    }
+    Ok(Arc::new(cookie_jar))
+}
+
+fn cookie_url(domain: &CookieDomain, path: &str, secure: Option<bool>) -> Result<Url> {
+    let domain = domain
+        .as_cow()
+        .ok_or_else(|| anyhow!("Cookie without a domain"))?;
+    let scheme = if secure == Some(true) {
+        "https"
+    } else {
+        "http"
+    };
+    Ok(Url::parse(&format!("{}://{}{}", scheme, domain, path))?)
+}
+
+fn netscape_line(cookie: &Cookie) -> Option<String> {
+    let (domain, include_subdomains) = match &cookie.domain {
+        CookieDomain::HostOnly(domain) => (domain.clone(), "FALSE"),
+        CookieDomain::Suffix(domain) => (format!(".{}", domain), "TRUE"),
+        _ => return None,
+    };
+    let expires = match &cookie.expires {
+        CookieExpiration::AtUtc(expires) => expires.unix_timestamp(),
+        CookieExpiration::SessionEnd => 0,
+    };
+    let prefix = if cookie.http_only() == Some(true) {
+        constant::NETSCAPE_HTTP_ONLY_PREFIX
+    } else {
+        ""
+    };
+    let secure = if cookie.secure() == Some(true) {
+        "TRUE"
+    } else {
+        "FALSE"
+    };
+    Some(format!(
+        "{}{}\t{}\t{}\t{}\t{}\t{}\t{}",
+        prefix,
+        domain,
+        include_subdomains,
+        &*cookie.path,
+        secure,
+        expires,
+        cookie.name(),
+        cookie.value()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NETSCAPE_COOKIES: &str = "# Netscape HTTP Cookie File\n\
+        .example.com\tTRUE\t/\tFALSE\t4102444800\tshared\tone\n\
+        #HttpOnly_example.com\tFALSE\t/account\tTRUE\t4102444800\tsession_id\ttwo\n\
+        example.com\tFALSE\t/\tFALSE\t0\ttransient\tthree\n";
+
+    // Cookie order is not part of the contract, so names are compared sorted.
+    fn request_cookies(jar: &CookieJar, url: &str) -> Vec<String> {
+        let url = Url::parse(url).unwrap();
+        let Some(value) = reqwest::cookie::CookieStore::cookies(jar, &url) else {
+            return Vec::new();
+        };
+        let mut cookies: Vec<String> = value
+            .to_str()
+            .unwrap()
+            .split("; ")
+            .map(String::from)
+            .collect();
+        cookies.sort();
+        cookies
+    }
+
+    fn assert_imported(jar: &CookieJar) {
+        assert_eq!(
+            request_cookies(jar, "https://example.com/account"),
+            ["session_id=two", "shared=one", "transient=three"]
+        );
+        assert_eq!(
+            request_cookies(jar, "http://cdn.example.com/"),
+            ["shared=one"]
+        );
+        assert_eq!(
+            request_cookies(jar, "http://example.com/account"),
+            ["shared=one", "transient=three"]
+        );
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("hermesdl-cookies-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn round_trips_netscape_cookies() {
+        let jar = CookieJar::default();
+        assert_eq!(jar.import(NETSCAPE_COOKIES).unwrap(), 3);
+        assert_imported(&jar);
+
+        let exported = jar.export(CookieFormat::Netscape).unwrap();
+        assert!(exported.contains("#HttpOnly_example.com\tFALSE\t/account\tTRUE\t4102444800"));
+        assert!(exported.contains("\t0\ttransient\tthree"));
+        let copy = CookieJar::default();
+        assert_eq!(copy.import(&exported).unwrap(), 3);
+        assert_imported(&copy);
+    }
+
+    #[test]
+    fn round_trips_json_cookies() {
+        let jar = CookieJar::default();
+        jar.import(NETSCAPE_COOKIES).unwrap();
+
+        let exported = jar.export(CookieFormat::Json).unwrap();
+        let copy = CookieJar::default();
+        assert_eq!(copy.import(&exported).unwrap(), 3);
+        assert_imported(&copy);
+    }
+
+    #[tokio::test]
+    async fn saves_concurrently_without_clobbering() {
+        let dir = temp_dir("save");
+        let jar = Arc::new(CookieJar::default());
+        jar.import(NETSCAPE_COOKIES).unwrap();
+        let path = dir.join("cookies.json");
+
+        let saves = (0..8).map(|_| {
+            let jar = jar.clone();
+            let path = path.clone();
+            tokio::spawn(async move { jar.save(&path).await })
+        });
+        for save in futures::future::join_all(saves).await {
+            save.unwrap().unwrap();
+        }
+
+        let entries = std::fs::read_dir(&dir).unwrap().count();
+        assert_eq!(entries, 1);
+        let copy = CookieJar::default();
+        assert_eq!(copy.load(&path).await.unwrap(), 3);
+        assert_imported(&copy);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,84 @@
+use super::constant;
+use anyhow::{anyhow, Result};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::ClientBuilder;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HeaderProfile {
+    // Sent in this order when loading pages, headers of a job replace values but keep their
+    // position.
+    pub headers: Vec<(String, String)>,
+    // Sent instead for files, ranges and segments, the page headers apply when empty.
+    pub fetch_headers: Vec<(String, String)>,
+    // Browsers send HTTP/1.1 header names in title case, except for client hints. Names are
+    // cased all alike, so profiles with client hints leave it off.
+    pub title_case: bool,
+}
+
+impl HeaderProfile {
+    pub fn new(headers: &[(&str, &str)], fetch_headers: &[(&str, &str)], title_case: bool) -> Self {
+        let owned = |headers: &[(&str, &str)]| {
+            headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()
+        };
+        Self {
+            headers: owned(headers),
+            fetch_headers: owned(fetch_headers),
+            title_case,
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        self.page_header_map()?;
+        self.fetch_header_map()?;
+        let client_hint = self
+            .headers
+            .iter()
+            .chain(&self.fetch_headers)
+            .find(|(name, _)| {
+                name.to_ascii_lowercase()
+                    .starts_with(constant::CLIENT_HINT_PREFIX)
+            });
+        match client_hint {
+            Some((name, _)) if self.title_case => Err(anyhow!(
+                "{} would be sent in title case, browsers send client hints in lower case",
+                name
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn page_header_map(&self) -> Result<HeaderMap> {
+        header_map(&self.headers)
+    }
+
+    pub fn fetch_header_map(&self) -> Result<HeaderMap> {
+        match self.fetch_headers.is_empty() {
+            true => header_map(&self.headers),
+            false => header_map(&self.fetch_headers),
+        }
+    }
+
+    pub fn apply(&self, builder: ClientBuilder) -> ClientBuilder {
+        if self.title_case {
+            builder.http1_title_case_headers()
+        } else {
+            builder
+        }
+    }
+}
+
+fn header_map(headers: &[(String, String)]) -> Result<HeaderMap> {
+    let mut header_map = HeaderMap::new();
+    for (name, value) in headers {
+        header_map.insert(
+            HeaderName::from_bytes(name.as_bytes())?,
+            HeaderValue::from_str(value)?,
+        );
+    }
+    Ok(header_map)
+}
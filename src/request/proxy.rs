@@ -0,0 +1,102 @@
+use super::constant;
+use super::tor::TorConfig;
+use anyhow::{anyhow, Result};
+use reqwest::{ClientBuilder, NoProxy, Proxy};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ProxyConfig {
+    pub url: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub no_proxy: Vec<String>,
+    // Falls back to HTTP_PROXY, HTTPS_PROXY, ALL_PROXY and NO_PROXY.
+    pub use_env: bool,
+    // Connections are spread over this many SOCKS credentials, Tor isolates each one
+    // on its own circuit.
+    pub isolation: usize,
+}
+
+impl ProxyConfig {
+    // The environment is ignored, so no host bypasses Tor by accident.
+    pub fn tor(tor: &TorConfig, circuits: usize) -> Self {
+        Self {
+            url: Some(tor.proxy.clone()),
+            use_env: false,
+            isolation: tor.circuits.unwrap_or(circuits),
+            ..Self::default()
+        }
+    }
+
+    // One config per isolated stream, each one with its own credentials.
+    pub fn isolated(&self) -> Vec<ProxyConfig> {
+        if self.isolation <= 1 || self.url.is_none() || self.username.is_some() {
+            return vec![self.clone()];
+        }
+        let session = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        (0..self.isolation)
+            .map(|index| ProxyConfig {
+                username: Some(format!(
+                    "{}-{}-{}",
+                    constant::ISOLATION_USERNAME,
+                    session,
+                    index
+                )),
+                password: Some(constant::ISOLATION_USERNAME.to_string()),
+                ..self.clone()
+            })
+            .collect()
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if let Some(url) = &self.url {
+            let scheme = Url::parse(url)?.scheme().to_string();
+            if !constant::PROXY_SCHEMES.contains(&scheme.as_str()) {
+                return Err(anyhow!("Unsupported proxy scheme: {}", scheme));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn apply(&self, builder: ClientBuilder) -> Result<ClientBuilder> {
+        let Some(url) = &self.url else {
+            // reqwest reads the proxy variables itself unless told otherwise.
+            return Ok(if self.use_env {
+                builder
+            } else {
+                builder.no_proxy()
+            });
+        };
+
+        self.validate()?;
+        let mut proxy = Proxy::all(url)?;
+        if let Some(username) = &self.username {
+            proxy = proxy.basic_auth(username, self.password.as_deref().unwrap_or_default());
+        }
+        let no_proxy = if self.no_proxy.is_empty() && self.use_env {
+            NoProxy::from_env()
+        } else {
+            NoProxy::from_string(&self.no_proxy.join(","))
+        };
+        Ok(builder.proxy(proxy.no_proxy(no_proxy)))
+    }
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            username: None,
+            password: None,
+            no_proxy: Vec::new(),
+            use_env: true,
+            isolation: 0,
+        }
+    }
+}
@@ -6,7 +6,7 @@ use futures_core::Stream;
 use reqwest::header::{
     HeaderName, ACCEPT_RANGES, CONTENT_DISPOSITION, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE,
 };
-use reqwest::Response as ReqwestResponse;
+use reqwest::{Response as ReqwestResponse, StatusCode};
 use tokio_util::io::StreamReader;
 
 #[derive(Debug)]
@@ -28,6 +28,10 @@ impl Response {
         None
     }
 
+    pub fn status(&self) -> StatusCode {
+        self.inner.status()
+    }
+
     pub fn accept_ranges(&self) -> Option<String> {
         self.get_from_header(ACCEPT_RANGES)
     }
@@ -57,18 +61,13 @@ impl Response {
         if let Some(encoding) = self.get_from_header(CONTENT_ENCODING) {
             Some(self.decompress(encoding.as_ref()).await)
         } else {
-            match self.inner.text().await {
-                Ok(text) => Some(text),
-                Err(_) => None,
-            }
+            self.inner.text().await.ok()
         }
     }
 
     async fn decompress(self, encoding: &str) -> String {
         let decoder = encoding.parse::<ContentDecoder>().unwrap();
-        let stream = self.inner.bytes_stream().map_err(|e| {
-            std::io::Error::new(std::io::ErrorKind::Other, e)
-        });
+        let stream = self.inner.bytes_stream().map_err(std::io::Error::other);
         let reader = StreamReader::new(stream);
         decoder.decode(reader).await
     }
@@ -0,0 +1,352 @@
+use super::constant;
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use reqwest::{Certificate, ClientBuilder, Identity};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{aws_lc_rs, verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, SignatureScheme};
+use rustls_platform_verifier::Verifier;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::sync::Arc;
+use webpki::EndEntityCert;
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    // PEM bundles trusted on top of the system roots.
+    pub ca_certs: Vec<String>,
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+    // Skips certificate verification, only meant for test hosts.
+    pub insecure: bool,
+    // "sha256/<base64>" hashes of public keys, one of them must be the key of the server
+    // certificate. Intermediates are whatever the server sends, so they are never pinned.
+    pub pins: Vec<String>,
+}
+
+impl TlsConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.client_cert.is_some() != self.client_key.is_some() {
+            return Err(anyhow!("client_cert and client_key must be given together"));
+        }
+        self.parse_pins()?;
+        Ok(())
+    }
+
+    pub fn apply(&self, builder: ClientBuilder) -> Result<ClientBuilder> {
+        let ca_pem = self.ca_pem()?;
+        let identity_pem = self.identity_pem()?;
+
+        if self.pins.is_empty() {
+            let mut builder = builder.tls_danger_accept_invalid_certs(self.insecure);
+            for certificate in Certificate::from_pem_bundle(&ca_pem)? {
+                builder = builder.add_root_certificate(certificate);
+            }
+            if let Some(identity_pem) = identity_pem {
+                builder = builder.identity(Identity::from_pem(&identity_pem)?);
+            }
+            return Ok(builder);
+        }
+
+        // reqwest has no pinning, so the rustls config is built here.
+        let provider = Arc::new(aws_lc_rs::default_provider());
+        let inner: Option<Arc<dyn ServerCertVerifier>> = if self.insecure {
+            None
+        } else {
+            let roots = CertificateDer::pem_slice_iter(&ca_pem).collect::<Result<Vec<_>, _>>()?;
+            Some(Arc::new(Verifier::new_with_extra_roots(
+                roots,
+                provider.clone(),
+            )?))
+        };
+        let verifier = PinnedVerifier {
+            inner,
+            pins: self.parse_pins()?,
+            provider: provider.clone(),
+        };
+
+        let tls = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier));
+        let mut tls = match identity_pem {
+            Some(identity_pem) => tls.with_client_auth_cert(
+                CertificateDer::pem_slice_iter(&identity_pem).collect::<Result<Vec<_>, _>>()?,
+                PrivateKeyDer::from_pem_slice(&identity_pem)?,
+            )?,
+            None => tls.with_no_client_auth(),
+        };
+        tls.alpn_protocols = constant::ALPN_PROTOCOLS
+            .iter()
+            .map(|protocol| protocol.to_vec())
+            .collect();
+        Ok(builder.tls_backend_preconfigured(tls))
+    }
+
+    fn ca_pem(&self) -> Result<Vec<u8>> {
+        let mut pem = Vec::new();
+        for path in &self.ca_certs {
+            pem.extend(
+                fs::read(path).map_err(|e| anyhow!("Failed to read CA bundle {}: {}", path, e))?,
+            );
+            pem.push(b'\n');
+        }
+        Ok(pem)
+    }
+
+    // Certificate and key in one buffer, as reqwest expects them.
+    fn identity_pem(&self) -> Result<Option<Vec<u8>>> {
+        let (Some(cert), Some(key)) = (&self.client_cert, &self.client_key) else {
+            return Ok(None);
+        };
+        let mut pem = fs::read(cert)
+            .map_err(|e| anyhow!("Failed to read client certificate {}: {}", cert, e))?;
+        pem.push(b'\n');
+        pem.extend(fs::read(key).map_err(|e| anyhow!("Failed to read client key {}: {}", key, e))?);
+        Ok(Some(pem))
+    }
+
+    // curl writes pins as "sha256//<base64>", both forms are accepted.
+    fn parse_pins(&self) -> Result<Vec<Vec<u8>>> {
+        self.pins
+            .iter()
+            .map(|pin| {
+                let encoded = pin
+                    .strip_prefix(constant::PIN_PREFIX)
+                    .ok_or_else(|| {
+                        anyhow!("Pin must start with {}: {}", constant::PIN_PREFIX, pin)
+                    })?
+                    .trim_start_matches('/');
+                let hash = STANDARD
+                    .decode(encoded)
+                    .map_err(|e| anyhow!("Invalid pin {}: {}", pin, e))?;
+                if hash.len() != constant::PIN_HASH_LENGTH {
+                    return Err(anyhow!("Invalid pin {}: not a SHA-256 hash", pin));
+                }
+                Ok(hash)
+            })
+            .collect()
+    }
+}
+
+fn spki_hash(certificate: &CertificateDer<'_>) -> Result<Vec<u8>, rustls::Error> {
+    let certificate =
+        EndEntityCert::try_from(certificate).map_err(|e| rustls::Error::General(e.to_string()))?;
+    Ok(Sha256::digest(certificate.subject_public_key_info().as_ref()).to_vec())
+}
+
+#[derive(Debug)]
+struct PinnedVerifier {
+    // None when verification is skipped and only the pins are checked.
+    inner: Option<Arc<dyn ServerCertVerifier>>,
+    pins: Vec<Vec<u8>>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if let Some(inner) = &self.inner {
+            inner.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+        }
+
+        let hash = spki_hash(end_entity)?;
+        if self.pins.iter().any(|pin| pin[..] == hash[..]) {
+            return Ok(ServerCertVerified::assertion());
+        }
+        Err(rustls::Error::General(format!(
+            "The public key of {} matches no pin",
+            server_name.to_str()
+        )))
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, Issuer, KeyPair};
+    use rustls::client::WebPkiServerVerifier;
+    use rustls::pki_types::PrivatePkcs8KeyDer;
+    use rustls::{ClientConnection, RootCertStore, ServerConfig, ServerConnection};
+
+    struct Pki {
+        ca: CertificateDer<'static>,
+        server: CertificateDer<'static>,
+        server_key: PrivateKeyDer<'static>,
+        // Signed by the same CA but for another host, like a certificate anyone can obtain.
+        other: CertificateDer<'static>,
+    }
+
+    fn pki() -> Pki {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+        let issuer = Issuer::new(ca_params, ca_key);
+
+        let server_key = KeyPair::generate().unwrap();
+        let server = CertificateParams::new(vec!["localhost".to_string()])
+            .unwrap()
+            .signed_by(&server_key, &issuer)
+            .unwrap();
+        let other_key = KeyPair::generate().unwrap();
+        let other = CertificateParams::new(vec!["other.test".to_string()])
+            .unwrap()
+            .signed_by(&other_key, &issuer)
+            .unwrap();
+
+        Pki {
+            ca: ca.der().clone(),
+            server: server.der().clone(),
+            server_key: PrivatePkcs8KeyDer::from(server_key.serialize_der()).into(),
+            other: other.der().clone(),
+        }
+    }
+
+    fn verifier(ca: &CertificateDer<'static>, pins: Vec<Vec<u8>>) -> PinnedVerifier {
+        let provider = Arc::new(aws_lc_rs::default_provider());
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.clone()).unwrap();
+        let inner = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+            .build()
+            .unwrap();
+        PinnedVerifier {
+            inner: Some(inner),
+            pins,
+            provider,
+        }
+    }
+
+    // Runs a TLS handshake in memory, the server sends the given chain.
+    fn handshake(
+        verifier: PinnedVerifier,
+        chain: Vec<CertificateDer<'static>>,
+        key: PrivateKeyDer<'static>,
+    ) -> Result<(), rustls::Error> {
+        let provider = Arc::new(aws_lc_rs::default_provider());
+        let client_config = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth();
+        let server_config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(chain, key)?;
+
+        let mut client = ClientConnection::new(
+            Arc::new(client_config),
+            ServerName::try_from("localhost").unwrap(),
+        )?;
+        let mut server = ServerConnection::new(Arc::new(server_config))?;
+        for _ in 0..10 {
+            if !client.is_handshaking() && !server.is_handshaking() {
+                return Ok(());
+            }
+            let mut buffer = Vec::new();
+            client.write_tls(&mut buffer).unwrap();
+            if !buffer.is_empty() {
+                server.read_tls(&mut buffer.as_slice()).unwrap();
+                server.process_new_packets()?;
+            }
+            buffer.clear();
+            server.write_tls(&mut buffer).unwrap();
+            if !buffer.is_empty() {
+                client.read_tls(&mut buffer.as_slice()).unwrap();
+                client.process_new_packets()?;
+            }
+        }
+        Err(rustls::Error::General(
+            "Handshake did not finish".to_string(),
+        ))
+    }
+
+    #[test]
+    fn accepts_the_pinned_server_key() {
+        let pki = pki();
+        let pin = spki_hash(&pki.server).unwrap();
+
+        handshake(
+            verifier(&pki.ca, vec![pin]),
+            vec![pki.server.clone()],
+            pki.server_key.clone_key(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn ignores_pinned_keys_appended_to_the_chain() {
+        let pki = pki();
+        let pin = spki_hash(&pki.other).unwrap();
+
+        let result = handshake(
+            verifier(&pki.ca, vec![pin]),
+            vec![pki.server.clone(), pki.other.clone()],
+            pki.server_key.clone_key(),
+        );
+        assert!(result.unwrap_err().to_string().contains("matches no pin"));
+    }
+
+    #[test]
+    fn parses_pins() {
+        let hash = STANDARD.encode([7u8; 32]);
+        let config = TlsConfig {
+            pins: vec![format!("sha256/{}", hash), format!("sha256//{}", hash)],
+            ..TlsConfig::default()
+        };
+        assert_eq!(config.parse_pins().unwrap(), vec![vec![7u8; 32]; 2]);
+
+        for pin in ["md5/abc", "sha256/not base64", "sha256/AAAA"] {
+            let config = TlsConfig {
+                pins: vec![pin.to_string()],
+                ..TlsConfig::default()
+            };
+            assert!(config.validate().is_err(), "{}", pin);
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use super::constant;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU32, Ordering};
+use tokio::fs;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TorConfig {
+    pub proxy: String,
+    // Segments are spread over this many circuits, by default one per concurrent segment.
+    pub circuits: Option<usize>,
+    pub control: Option<TorControlConfig>,
+    pub newnym_after_failures: u32,
+}
+
+impl Default for TorConfig {
+    fn default() -> Self {
+        Self {
+            proxy: constant::TOR_PROXY_SCHEME.to_string(),
+            circuits: None,
+            control: None,
+            newnym_after_failures: constant::TOR_NEWNYM_AFTER_FAILURES,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TorControlConfig {
+    pub address: String,
+    pub password: Option<String>,
+    pub cookie_path: Option<String>,
+}
+
+impl Default for TorControlConfig {
+    fn default() -> Self {
+        Self {
+            address: constant::TOR_CONTROL_ADDRESS.to_string(),
+            password: None,
+            cookie_path: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TorController {
+    config: TorControlConfig,
+}
+
+impl TorController {
+    pub fn new(config: TorControlConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn new_identity(&self) -> Result<()> {
+        timeout(constant::TOR_CONTROL_TIMEOUT, self.signal_newnym())
+            .await
+            .map_err(|_| anyhow!("Tor control port {} timed out", self.config.address))?
+    }
+
+    async fn signal_newnym(&self) -> Result<()> {
+        let mut stream = BufReader::new(TcpStream::connect(&self.config.address).await?);
+
+        let authenticate = match (&self.config.password, &self.config.cookie_path) {
+            (Some(password), _) => format!(
+                "AUTHENTICATE \"{}\"",
+                password.replace('\\', "\\\\").replace('"', "\\\"")
+            ),
+            (None, Some(cookie_path)) => {
+                format!("AUTHENTICATE {}", hex::encode(fs::read(cookie_path).await?))
+            }
+            (None, None) => "AUTHENTICATE".to_string(),
+        };
+        command(&mut stream, &authenticate).await?;
+        command(&mut stream, "SIGNAL NEWNYM").await?;
+        command(&mut stream, "QUIT").await
+    }
+}
+
+// Counts failed segments of a job and asks Tor for new circuits once too many failed.
+#[derive(Debug)]
+pub struct CircuitRenewal {
+    controller: TorController,
+    threshold: u32,
+    failures: AtomicU32,
+}
+
+impl CircuitRenewal {
+    pub fn new(controller: TorController, threshold: u32) -> Self {
+        Self {
+            controller,
+            threshold: threshold.max(1),
+            failures: AtomicU32::new(0),
+        }
+    }
+
+    // True once Tor switched to new circuits, connections must be opened again to use them.
+    pub async fn record_failure(&self) -> bool {
+        if self.failures.fetch_add(1, Ordering::SeqCst) + 1 < self.threshold {
+            return false;
+        }
+        self.failures.store(0, Ordering::SeqCst);
+        match self.controller.new_identity().await {
+            Ok(_) => {
+                eprintln!("Requested new Tor circuits");
+                true
+            }
+            Err(e) => {
+                eprintln!("Failed to request new Tor circuits: {}", e);
+                false
+            }
+        }
+    }
+}
+
+async fn command(stream: &mut BufReader<TcpStream>, command: &str) -> Result<()> {
+    stream
+        .get_mut()
+        .write_all(format!("{}\r\n", command).as_bytes())
+        .await?;
+
+    // Lines of a multi-line reply continue with "250-", the last one is "250 ".
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if stream.read_line(&mut line).await? == 0 {
+            return Err(anyhow!("Tor control port closed the connection"));
+        }
+        if line.as_bytes().get(3) != Some(&b'-') {
+            break;
+        }
+    }
+
+    if line.starts_with("250") {
+        Ok(())
+    } else {
+        Err(anyhow!("Tor control port answered: {}", line.trim()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+    use tokio::sync::Mutex;
+
+    // Answers every command with 250 and records the commands of each connection.
+    async fn fake_control_port() -> (String, Arc<Mutex<Vec<Vec<String>>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let sessions = Arc::new(Mutex::new(Vec::new()));
+        let recorded = sessions.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                let mut socket = BufReader::new(socket);
+                let mut commands = Vec::new();
+                let mut line = String::new();
+                while socket.read_line(&mut line).await.unwrap_or(0) > 0 {
+                    commands.push(line.trim_end().to_string());
+                    let quit = line.starts_with("QUIT");
+                    line.clear();
+                    socket.get_mut().write_all(b"250 OK\r\n").await.unwrap();
+                    if quit {
+                        break;
+                    }
+                }
+                recorded.lock().await.push(commands);
+            }
+        });
+
+        (address, sessions)
+    }
+
+    #[tokio::test]
+    async fn requests_new_circuits_after_failures() {
+        let (address, sessions) = fake_control_port().await;
+        let controller = TorController::new(TorControlConfig {
+            address,
+            password: Some("pass\"word".to_string()),
+            cookie_path: None,
+        });
+        let renewal = CircuitRenewal::new(controller, 3);
+
+        assert!(!renewal.record_failure().await);
+        assert!(!renewal.record_failure().await);
+        assert!(sessions.lock().await.is_empty());
+        assert!(renewal.record_failure().await);
+
+        assert_eq!(
+            *sessions.lock().await,
+            [["AUTHENTICATE \"pass\\\"word\"", "SIGNAL NEWNYM", "QUIT"]]
+        );
+        assert!(!renewal.record_failure().await);
+    }
+
+    #[tokio::test]
+    async fn reports_rejected_authentication() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 256];
+            let _ = tokio::io::AsyncReadExt::read(&mut socket, &mut buf).await;
+            let _ = socket
+                .write_all(b"515 Authentication failed: Password did not match\r\n")
+                .await;
+        });
+        let controller = TorController::new(TorControlConfig {
+            address,
+            ..TorControlConfig::default()
+        });
+
+        let error = controller.new_identity().await.unwrap_err();
+        assert!(error.to_string().contains("515"));
+    }
+}
@@ -1,7 +1,8 @@
 use super::constant;
-use anyhow::{anyhow, Error};
-use reqwest::header::HeaderValue;
+use super::header_profile::HeaderProfile;
+use anyhow::{anyhow, Error, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Formatter;
 use std::str::FromStr;
@@ -10,23 +11,41 @@ use std::str::FromStr;
 pub enum UserAgent {
     Firefox,
     Chrome,
+    // Only the User-Agent header is sent.
+    Custom(String),
+    // One of the header profiles defined in the config.
+    Profile(String),
 }
 
 impl UserAgent {
-    fn to_header_value(&self) -> &'static str {
+    pub fn header_profile(
+        &self,
+        profiles: &HashMap<String, HeaderProfile>,
+    ) -> Result<HeaderProfile> {
         match self {
-            UserAgent::Firefox => constant::FIREFOX_USER_AGENT,
-            UserAgent::Chrome => constant::CHROME_USER_AGENT,
+            UserAgent::Firefox => Ok(HeaderProfile::new(
+                &constant::FIREFOX_PAGE_HEADERS,
+                &constant::FIREFOX_FETCH_HEADERS,
+                true,
+            )),
+            UserAgent::Chrome => Ok(HeaderProfile::new(
+                &constant::CHROME_PAGE_HEADERS,
+                &constant::CHROME_FETCH_HEADERS,
+                false,
+            )),
+            UserAgent::Custom(user_agent) => Ok(HeaderProfile::new(
+                &[(constant::USER_AGENT_HEADER, user_agent)],
+                &[],
+                false,
+            )),
+            UserAgent::Profile(name) => profiles
+                .get(name)
+                .cloned()
+                .ok_or_else(|| anyhow!("Unknown header profile: {}", name)),
         }
     }
 }
 
-impl Into<HeaderValue> for UserAgent {
-    fn into(self) -> HeaderValue {
-        HeaderValue::from_static(self.to_header_value())
-    }
-}
-
 impl FromStr for UserAgent {
     type Err = Error;
 
@@ -44,6 +63,8 @@ impl fmt::Display for UserAgent {
         let s = match self {
             UserAgent::Firefox => constant::FIREFOX,
             UserAgent::Chrome => constant::CHROME,
+            UserAgent::Custom(user_agent) => user_agent,
+            UserAgent::Profile(name) => name,
         };
         write!(f, "{}", s)
     }
@@ -1,3 +1,4 @@
+mod cli;
 pub mod config;
 mod constant;
 pub mod controller;
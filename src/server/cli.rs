@@ -0,0 +1,199 @@
+use super::config::{merge_patch, Config};
+use super::constant;
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use serde_json::{Map, Value};
+use std::env;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+#[command(name = "HermesDL", version, about = "Download server")]
+pub struct Cli {
+    #[arg(long, env = "HERMESDL_CONFIG", help = "Path of the config file")]
+    pub config: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env = "HERMESDL_ADDRESS",
+        default_value = constant::DEFAULT_SERVER_ADDRESS,
+        help = "Address to listen on"
+    )]
+    pub address: IpAddr,
+
+    #[arg(
+        long,
+        env = "HERMESDL_PORT",
+        default_value_t = constant::DEFAULT_SERVER_PORT,
+        help = "Port to listen on"
+    )]
+    pub port: u16,
+
+    #[arg(
+        long = "set",
+        value_name = "KEY=VALUE",
+        help = "Override a config field, e.g. --set chunk_size=5000000 or --set writer.fsync_policy=never"
+    )]
+    pub overrides: Vec<String>,
+
+    #[arg(
+        long = "set-json",
+        value_name = "KEY=JSON",
+        help = "Override a config field with a JSON value, e.g. --set-json 'upload={\"bucket\":\"media\"}'"
+    )]
+    pub json_overrides: Vec<String>,
+
+    #[arg(long, help = "Print the effective config and exit")]
+    pub print_config: bool,
+}
+
+impl Cli {
+    // An explicit path wins, then a config.json in the working directory, then the XDG location.
+    pub fn config_path(&self) -> PathBuf {
+        if let Some(path) = &self.config {
+            return path.clone();
+        }
+        let local_path = PathBuf::from(constant::CONFIG_FILE_NAME);
+        if local_path.exists() {
+            return local_path;
+        }
+        match dirs::config_dir() {
+            Some(dir) => dir
+                .join(constant::CONFIG_DIR_NAME)
+                .join(constant::CONFIG_FILE_NAME),
+            None => local_path,
+        }
+    }
+
+    // Environment variables apply first, so command line flags take precedence over them.
+    pub fn overrides(&self) -> Result<Value> {
+        let defaults = serde_json::to_value(Config::new())?;
+        let known_fields = match &defaults {
+            Value::Object(fields) => fields.clone(),
+            _ => Map::new(),
+        };
+        let mut overrides = Value::Object(Map::new());
+
+        for (key, value) in env::vars() {
+            let Some(field) = key.strip_prefix(constant::ENV_PREFIX) else {
+                continue;
+            };
+            if constant::SERVER_ENV_VARS.contains(&field) {
+                continue;
+            }
+            let path: Vec<String> = field.to_lowercase().split("__").map(String::from).collect();
+            if !known_fields.contains_key(&path[0]) {
+                eprintln!("Ignoring unknown config field in {}", key);
+                continue;
+            }
+            let value = parse_value(&defaults, &path, &value);
+            merge_patch(&mut overrides, nested(&path, value));
+        }
+
+        for entry in &self.overrides {
+            let (field, value) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Expected KEY=VALUE, got {}", entry))?;
+            let path: Vec<String> = field.split('.').map(String::from).collect();
+            if !known_fields.contains_key(&path[0]) {
+                return Err(anyhow!("Unknown config field: {}", field));
+            }
+            let value = parse_value(&defaults, &path, value);
+            merge_patch(&mut overrides, nested(&path, value));
+        }
+
+        // Explicit JSON comes last, a null here resets the field to its default.
+        for entry in &self.json_overrides {
+            let (field, value) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Expected KEY=JSON, got {}", entry))?;
+            let path: Vec<String> = field.split('.').map(String::from).collect();
+            if !known_fields.contains_key(&path[0]) {
+                return Err(anyhow!("Unknown config field: {}", field));
+            }
+            let value = serde_json::from_str(value)
+                .map_err(|e| anyhow!("Invalid JSON for {}: {}", field, e))?;
+            merge_patch(&mut overrides, nested(&path, value));
+        }
+
+        Ok(overrides)
+    }
+}
+
+// The default config tells the type of the field: string fields keep the value as is, other
+// fields take it as JSON. Fields without a default (e.g. inside upload) only take JSON objects
+// and arrays, anything else stays a string. A null never comes from here, so nothing is deleted.
+fn parse_value(defaults: &Value, path: &[String], value: &str) -> Value {
+    let string = || Value::String(value.to_string());
+    match defaults.pointer(&format!("/{}", path.join("/"))) {
+        Some(Value::String(_)) => string(),
+        Some(Value::Null) | None => match serde_json::from_str(value) {
+            Ok(parsed @ (Value::Object(_) | Value::Array(_))) => parsed,
+            _ => string(),
+        },
+        Some(_) => match serde_json::from_str(value) {
+            Ok(Value::Null) | Err(_) => string(),
+            Ok(parsed) => parsed,
+        },
+    }
+}
+
+fn nested(path: &[String], value: Value) -> Value {
+    path.iter().rev().fold(value, |value, key| {
+        Value::Object(Map::from_iter([(key.clone(), value)]))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse(args: &[&str]) -> Result<Value> {
+        Cli::parse_from([&["hermesdl"], args].concat()).overrides()
+    }
+
+    #[test]
+    fn parses_values_by_field_type() {
+        let overrides = parse(&[
+            "--set",
+            "download_dir=2024",
+            "--set",
+            "filename_template=null",
+            "--set",
+            "chunk_size=5000000",
+            "--set",
+            "use_tor=true",
+            "--set",
+            "upload={\"bucket\":\"media\"}",
+            "--set",
+            "proxy.url=123",
+        ])
+        .unwrap();
+
+        assert_eq!(overrides["download_dir"], json!("2024"));
+        assert_eq!(overrides["filename_template"], json!("null"));
+        assert_eq!(overrides["chunk_size"], json!(5000000));
+        assert_eq!(overrides["use_tor"], json!(true));
+        assert_eq!(overrides["upload"], json!({ "bucket": "media" }));
+        assert_eq!(overrides["proxy"]["url"], json!("123"));
+    }
+
+    #[test]
+    fn takes_explicit_json() {
+        let overrides = parse(&[
+            "--set",
+            "upload.bucket=media",
+            "--set-json",
+            "upload.part_size=8388608",
+        ])
+        .unwrap();
+        assert_eq!(
+            overrides["upload"],
+            json!({ "bucket": "media", "part_size": 8388608 })
+        );
+
+        assert!(parse(&["--set-json", "chunk_size=five"]).is_err());
+        assert!(parse(&["--set", "unknown=1"]).is_err());
+    }
+}
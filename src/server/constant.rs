@@ -1,3 +1,24 @@
-pub const CONFIG_PATH: &str = "config.json";
-pub const SERVER_ADDRESS: [u8; 4] = [127, 0, 0, 1];
-pub const SERVER_PORT: u16 = 3030;
+use std::time::Duration;
+
+pub const CONFIG_FILE_NAME: &str = "config.json";
+pub const CONFIG_DIR_NAME: &str = "hermesdl";
+pub const CONFIG_TEMP_EXTENSION: &str = "tmp";
+pub const CONFIG_BACKUP_EXTENSION: &str = "bak";
+pub const CONFIG_WATCH_INTERVAL: Duration = Duration::from_secs(2);
+pub const DEFAULT_SERVER_ADDRESS: &str = "127.0.0.1";
+pub const DEFAULT_SERVER_PORT: u16 = 3030;
+pub const ENV_PREFIX: &str = "HERMESDL_";
+pub const SERVER_ENV_VARS: [&str; 3] = ["CONFIG", "ADDRESS", "PORT"];
+pub const DEFAULT_DOWNLOAD_DIR: &str = "files";
+pub const DEFAULT_FILENAME_TEMPLATE: &str = "{filename}";
+pub const REDACTED: &str = "********";
+// Fields telling apart entries of a list, secrets are only kept for the same entry.
+pub const IDENTITY_FIELDS: [&str; 3] = ["name", "host", "username"];
+pub const SECRET_FIELDS: [&str; 6] = [
+    "password",
+    "secret_key",
+    "token",
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+];
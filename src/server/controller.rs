@@ -1,9 +1,22 @@
 use crate::downloader::dto::DownloadInfo;
+use crate::downloader::import;
 use crate::downloader::manager;
-use crate::server::config::{Config, SharedConfig};
+use crate::downloader::profile::Profile;
+use crate::downloader::strategy::SharedRegistry;
+use crate::downloader::upload::S3Uploader;
+use crate::request::auth::CredentialStore;
+use crate::request::client::Client;
+use crate::request::cookie_jar::{CookieFormat, SharedCookieJar};
+use crate::request::proxy::ProxyConfig;
+use crate::request::tor::{CircuitRenewal, TorController};
+use crate::server::config::{Config, SharedConfig, ValidationError};
 use anyhow::Result;
+use bytes::Bytes;
+use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::convert::Infallible;
+use std::path::Path;
+use warp::http::StatusCode;
 use warp::{Filter, Reply};
 
 pub fn with_shared_config(
@@ -12,6 +25,18 @@ pub fn with_shared_config(
     warp::any().map(move || shared_config.clone())
 }
 
+pub fn with_shared_registry(
+    shared_registry: SharedRegistry,
+) -> impl Filter<Extract = (SharedRegistry,), Error = Infallible> + Clone {
+    warp::any().map(move || shared_registry.clone())
+}
+
+pub fn with_shared_cookie_jar(
+    shared_cookie_jar: SharedCookieJar,
+) -> impl Filter<Extract = (SharedCookieJar,), Error = Infallible> + Clone {
+    warp::any().map(move || shared_cookie_jar.clone())
+}
+
 fn modify_header(header: &mut HashMap<String, String>) {
     let keys = [
         "Cache-Control",
@@ -25,37 +50,291 @@ fn modify_header(header: &mut HashMap<String, String>) {
     }
 }
 
-pub async fn init_download(
+// A proxy given by the job wins, then the profile's, then Tor and at last the global proxy.
+fn select_proxy(
+    config: &Config,
+    profile: &Profile,
+    job_proxy: Option<&ProxyConfig>,
+    circuits: usize,
+) -> ProxyConfig {
+    job_proxy
+        .or(profile.proxy.as_ref())
+        .cloned()
+        .or_else(|| {
+            uses_tor(config, profile, job_proxy).then(|| ProxyConfig::tor(&config.tor, circuits))
+        })
+        .or_else(|| config.proxy.clone())
+        .unwrap_or_default()
+}
+
+fn uses_tor(config: &Config, profile: &Profile, job_proxy: Option<&ProxyConfig>) -> bool {
+    job_proxy.or(profile.proxy.as_ref()).is_none() && profile.use_tor.unwrap_or(config.use_tor)
+}
+
+// Settings of the matching profile take precedence over the global ones.
+fn create_downloader(
+    config: &Config,
+    profile: Option<&Profile>,
+    info: &DownloadInfo,
+    shared_registry: SharedRegistry,
+    shared_cookie_jar: &SharedCookieJar,
+) -> Result<manager::Downloader> {
+    let profile = profile.cloned().unwrap_or_default();
+    let job_proxy = info.proxy.as_ref();
+    let max_concurrent_count = profile
+        .max_concurrent_count
+        .unwrap_or(config.max_concurrent_count);
+    let renewal = match &config.tor.control {
+        Some(control) if uses_tor(config, &profile, job_proxy) => Some(CircuitRenewal::new(
+            TorController::new(control.clone()),
+            config.tor.newnym_after_failures,
+        )),
+        _ => None,
+    };
+
+    let client = Client::new(
+        &select_proxy(config, &profile, job_proxy, max_concurrent_count),
+        &profile
+            .user_agent
+            .as_ref()
+            .unwrap_or(&config.user_agent)
+            .header_profile(&config.header_profiles)?,
+        profile.tls.as_ref().unwrap_or(&config.tls),
+        shared_cookie_jar,
+    )?
+    .with_credentials(CredentialStore::load(
+        &info.url,
+        info.auth.clone(),
+        &config.auth,
+    )?);
+
+    // Uploads go to storage of the user, so the global proxy applies.
+    let uploader = config
+        .upload
+        .clone()
+        .map(|upload| S3Uploader::new(upload, &config.proxy.clone().unwrap_or_default()))
+        .transpose()?;
+
+    Ok(manager::Downloader::new(
+        client,
+        profile.chunk_size.unwrap_or(config.chunk_size),
+        max_concurrent_count,
+        &config.download_dir,
+        &config.filename_template,
+        shared_registry,
+    )
+    .with_categories(config.categories.clone())
+    .with_preallocate(config.preallocate)
+    .with_writer_options(config.writer.clone())
+    .with_sinks(config.sinks.clone())
+    .with_uploader(uploader)
+    .with_circuit_renewal(renewal))
+}
+
+async fn run_download(
     mut info: DownloadInfo,
     shared_config: SharedConfig,
-) -> Result<impl Reply, Infallible> {
-    let config = shared_config.read().await;
-    let downloader = manager::Downloader::new(
-        config.use_tor,
-        &config.user_agent,
-        config.chunk_size,
-        config.max_concurrent_count,
-    );
+    shared_registry: SharedRegistry,
+    shared_cookie_jar: SharedCookieJar,
+) {
+    let (downloader, profile_headers) = {
+        let config = shared_config.read().await;
+        let profile = config.profiles.iter().find(|p| p.matches(&info.url));
+        let headers = profile.map(|p| p.headers.clone()).unwrap_or_default();
+        (
+            create_downloader(&config, profile, &info, shared_registry, &shared_cookie_jar),
+            headers,
+        )
+    };
+    let downloader = match downloader {
+        Ok(downloader) => downloader,
+        Err(e) => {
+            eprintln!("{e}");
+            return;
+        }
+    };
 
     if let Some(ref mut headers) = info.headers {
         modify_header(headers);
     };
+    if !profile_headers.is_empty() {
+        let headers = info.headers.get_or_insert_with(HashMap::new);
+        for (key, value) in profile_headers {
+            headers.insert(key.to_lowercase(), value);
+        }
+    }
 
-    if let Err(e) = downloader
-        .download_file(info.url.as_str(), info.headers.as_ref())
-        .await
-    {
+    if let Some(cookies) = &info.cookies {
+        if let Err(e) = shared_cookie_jar.add(&info.url, cookies) {
+            eprintln!("{e}");
+            return;
+        }
+    }
+
+    if let Err(e) = downloader.download(&info).await {
         eprintln!("{e}");
     }
+
+    let cookie_file = {
+        let config = shared_config.read().await;
+        config
+            .cookies
+            .file
+            .clone()
+            .filter(|_| config.cookies.persist)
+    };
+    if let Some(file) = cookie_file {
+        if let Err(e) = shared_cookie_jar.save(Path::new(&file)).await {
+            eprintln!("Failed to save cookies to {}: {}", file, e);
+        }
+    }
+}
+
+fn spawn_batch(
+    infos: Vec<DownloadInfo>,
+    shared_config: SharedConfig,
+    shared_registry: SharedRegistry,
+    shared_cookie_jar: SharedCookieJar,
+) -> impl Reply {
+    let accepted = infos.len();
+    // Jobs run one after another, each one already downloads its segments in parallel.
+    tokio::spawn(async move {
+        for info in infos {
+            run_download(
+                info,
+                shared_config.clone(),
+                shared_registry.clone(),
+                shared_cookie_jar.clone(),
+            )
+            .await;
+        }
+    });
+    warp::reply::with_status(
+        warp::reply::json(&json!({ "accepted": accepted })),
+        StatusCode::ACCEPTED,
+    )
+}
+
+pub async fn init_download(
+    info: DownloadInfo,
+    shared_config: SharedConfig,
+    shared_registry: SharedRegistry,
+    shared_cookie_jar: SharedCookieJar,
+) -> Result<impl Reply, Infallible> {
+    run_download(info, shared_config, shared_registry, shared_cookie_jar).await;
     Ok("success")
 }
 
-pub async fn update_config(
-    new_config: Config,
+pub async fn init_batch_download(
+    infos: Vec<DownloadInfo>,
     shared_config: SharedConfig,
+    shared_registry: SharedRegistry,
+    shared_cookie_jar: SharedCookieJar,
 ) -> Result<impl Reply, Infallible> {
-    if let Err(e) = Config::update(new_config, shared_config).await {
-        eprintln!("{e}");
+    Ok(spawn_batch(
+        infos,
+        shared_config,
+        shared_registry,
+        shared_cookie_jar,
+    ))
+}
+
+pub async fn import_downloads(
+    body: Bytes,
+    shared_config: SharedConfig,
+    shared_registry: SharedRegistry,
+    shared_cookie_jar: SharedCookieJar,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let input = String::from_utf8_lossy(&body);
+    match import::parse_input_file(&input) {
+        Ok(infos) => Ok(Box::new(spawn_batch(
+            infos,
+            shared_config,
+            shared_registry,
+            shared_cookie_jar,
+        ))),
+        Err(e) => Ok(Box::new(warp::reply::with_status(
+            e.to_string(),
+            StatusCode::BAD_REQUEST,
+        ))),
+    }
+}
+
+fn config_reply(result: Result<Config>) -> Box<dyn Reply> {
+    match result {
+        Ok(config) => match config.redacted() {
+            Ok(config) => Box::new(warp::reply::json(&config)),
+            Err(e) => config_reply(Err(e)),
+        },
+        Err(e) => match e.downcast_ref::<ValidationError>() {
+            Some(validation_error) => Box::new(warp::reply::with_status(
+                warp::reply::json(validation_error),
+                StatusCode::BAD_REQUEST,
+            )),
+            None => {
+                eprintln!("{e}");
+                Box::new(warp::reply::with_status(
+                    warp::reply::json(&json!({ "error": e.to_string() })),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ))
+            }
+        },
+    }
+}
+
+pub async fn get_config(shared_config: SharedConfig) -> Result<Box<dyn Reply>, Infallible> {
+    let config = shared_config.read().await.clone();
+    Ok(config_reply(Ok(config)))
+}
+
+pub async fn update_config(
+    new_config: Value,
+    shared_config: SharedConfig,
+) -> Result<Box<dyn Reply>, Infallible> {
+    Ok(config_reply(shared_config.update(new_config).await))
+}
+
+pub async fn patch_config(
+    patch: Value,
+    shared_config: SharedConfig,
+) -> Result<Box<dyn Reply>, Infallible> {
+    Ok(config_reply(shared_config.patch(patch).await))
+}
+
+pub async fn get_cookies(
+    query: HashMap<String, String>,
+    shared_cookie_jar: SharedCookieJar,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let format = match query.get("format").map(String::as_str) {
+        Some("json") => CookieFormat::Json,
+        _ => CookieFormat::Netscape,
     };
-    Ok("success")
+    match shared_cookie_jar.export(format) {
+        Ok(cookies) => Ok(Box::new(cookies)),
+        Err(e) => Ok(Box::new(warp::reply::with_status(
+            e.to_string(),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ))),
+    }
+}
+
+pub async fn import_cookies(
+    body: Bytes,
+    shared_cookie_jar: SharedCookieJar,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let input = String::from_utf8_lossy(&body);
+    match shared_cookie_jar.import(&input) {
+        Ok(imported) => Ok(Box::new(warp::reply::json(
+            &json!({ "imported": imported }),
+        ))),
+        Err(e) => Ok(Box::new(warp::reply::with_status(
+            e.to_string(),
+            StatusCode::BAD_REQUEST,
+        ))),
+    }
+}
+
+pub async fn clear_cookies(shared_cookie_jar: SharedCookieJar) -> Result<impl Reply, Infallible> {
+    shared_cookie_jar.clear();
+    Ok(StatusCode::NO_CONTENT)
 }
@@ -1,27 +1,122 @@
-use super::config::create_shared_config;
-use super::constant;
-use super::controller::{init_download, update_config, with_shared_config};
+use super::cli::Cli;
+use super::config::{create_shared_config, SharedConfig};
+use super::controller::{
+    clear_cookies, get_config, get_cookies, import_cookies, import_downloads, init_batch_download,
+    init_download, patch_config, update_config, with_shared_config, with_shared_cookie_jar,
+    with_shared_registry,
+};
+use crate::downloader::strategy::create_shared_registry;
+use crate::request::cookie_jar::create_shared_cookie_jar;
+use anyhow::Result;
+use clap::Parser;
+use std::process;
 use warp::Filter;
 
+async fn load_config(cli: &Cli) -> Result<SharedConfig> {
+    create_shared_config(cli.config_path(), cli.overrides()?).await
+}
+
 pub async fn run_server() {
-    let shared_config = create_shared_config().await;
+    let cli = Cli::parse();
+    let shared_config = match load_config(&cli).await {
+        Ok(shared_config) => shared_config,
+        Err(e) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+    };
+    if cli.print_config {
+        let config = shared_config.read().await.redacted();
+        match config.and_then(|config| Ok(serde_json::to_string_pretty(&config)?)) {
+            Ok(config) => println!("{config}"),
+            Err(e) => eprintln!("{e}"),
+        }
+        return;
+    }
+    let shared_registry = create_shared_registry();
+    let cookie_config = shared_config.read().await.cookies.clone();
+    let shared_cookie_jar = match create_shared_cookie_jar(&cookie_config).await {
+        Ok(shared_cookie_jar) => shared_cookie_jar,
+        Err(e) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+    };
+    shared_config.clone().watch();
 
     let download_route = warp::post()
         .and(warp::path("download"))
         .and(warp::body::json())
         .and(with_shared_config(shared_config.clone()))
+        .and(with_shared_registry(shared_registry.clone()))
+        .and(with_shared_cookie_jar(shared_cookie_jar.clone()))
         .and_then(init_download);
 
+    let batch_download_route = warp::post()
+        .and(warp::path!("downloads" / "batch"))
+        .and(warp::body::json())
+        .and(with_shared_config(shared_config.clone()))
+        .and(with_shared_registry(shared_registry.clone()))
+        .and(with_shared_cookie_jar(shared_cookie_jar.clone()))
+        .and_then(init_batch_download);
+
+    let import_download_route = warp::post()
+        .and(warp::path!("downloads" / "import"))
+        .and(warp::body::bytes())
+        .and(with_shared_config(shared_config.clone()))
+        .and(with_shared_registry(shared_registry.clone()))
+        .and(with_shared_cookie_jar(shared_cookie_jar.clone()))
+        .and_then(import_downloads);
+
     let update_config_route = warp::put()
         .and(warp::path("config"))
         .and(warp::body::json())
         .and(with_shared_config(shared_config.clone()))
         .and_then(update_config);
 
-    let routes = download_route.or(update_config_route);
+    let get_config_route = warp::get()
+        .and(warp::path("config"))
+        .and(with_shared_config(shared_config.clone()))
+        .and_then(get_config);
+
+    let patch_config_route = warp::patch()
+        .and(warp::path("config"))
+        .and(warp::body::json())
+        .and(with_shared_config(shared_config.clone()))
+        .and_then(patch_config);
+
+    let get_cookies_route = warp::get()
+        .and(warp::path("cookies"))
+        .and(warp::query())
+        .and(with_shared_cookie_jar(shared_cookie_jar.clone()))
+        .and_then(get_cookies);
+
+    let import_cookies_route = warp::post()
+        .and(warp::path("cookies"))
+        .and(warp::body::bytes())
+        .and(with_shared_cookie_jar(shared_cookie_jar.clone()))
+        .and_then(import_cookies);
+
+    let clear_cookies_route = warp::delete()
+        .and(warp::path("cookies"))
+        .and(with_shared_cookie_jar(shared_cookie_jar.clone()))
+        .and_then(clear_cookies);
+
+    let routes = download_route
+        .or(batch_download_route)
+        .or(import_download_route)
+        .or(update_config_route)
+        .or(get_config_route)
+        .or(patch_config_route)
+        .or(get_cookies_route)
+        .or(import_cookies_route)
+        .or(clear_cookies_route);
 
-    println!("Start Server");
-    warp::serve(routes)
-        .run((constant::SERVER_ADDRESS, constant::SERVER_PORT))
-        .await;
+    eprintln!(
+        "Start Server on {}:{} with {}",
+        cli.address,
+        cli.port,
+        shared_config.path().display()
+    );
+    warp::serve(routes).run((cli.address, cli.port)).await;
 }